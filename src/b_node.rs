@@ -1,31 +1,74 @@
+use crate::compare::compare_keys;
+use crate::error::{DbError, Result};
+use crate::key_encoding::KeyEncoding;
+use crate::overflow::{self, OVERFLOW_TYPE};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::ops::Bound;
+
 //Constants used to work with raw pointers
-const HEADER: u8 = 4;
-const BTREE_PAGE_SIZE: u16 = 4096;
+pub(crate) const HEADER: u8 = 4;
+//The page size is picked when a database file is created and recorded in its meta page.
+//It has to be a power of two between MIN_PAGE_SIZE and MAX_PAGE_SIZE, so offsets within a
+//page always fit in 2 bytes
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 4096;
+pub(crate) const MIN_PAGE_SIZE: usize = 4096;
+pub(crate) const MAX_PAGE_SIZE: usize = 65536;
+//The last bytes of every page but the meta page hold a CRC32 of the rest of the page.
+//The pager writes and checks it, page formats only use the bytes before it
+pub(crate) const CHECKSUM_SIZE: usize = 4;
+//Size limits are based on the smallest page, a single kv pair always fits in any page
 const BTREE_MAX_KEY_SIZE: u16 = 1000;
 const BTREE_MAX_VAL_SIZE: u16 = 3000;
 
-trait Tree {
-    fn get(pointer: u64) -> BNode;
-    fn new(node: BNode) -> u64;
-    fn del(pointer: u64);
+pub(crate) fn valid_page_size(page_size: usize) -> bool {
+    page_size.is_power_of_two() && (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size)
+}
+
+//Storage for the pages of a tree, addressed by page pointers
+#[allow(clippy::new_ret_no_self, clippy::wrong_self_convention)]
+pub trait Tree {
+    //Load the node stored at pointer
+    fn get(&self, pointer: u64) -> Result<BNode>;
+    //Store a new node and return its pointer
+    fn new(&mut self, node: BNode) -> u64;
+    //Release the page at pointer, it is not referenced by the tree anymore.
+    //Nodes are never modified in place, an updated node is always stored with new and the
+    //old one released with del, so the store decides when an old page may be reused
+    fn del(&mut self, pointer: u64);
+    //Size of every page in the store
+    fn page_size(&self) -> usize;
 }
 
-enum BNodeType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BNodeType {
     InternalNode,
     LeafNode,
 }
 
 impl BNodeType {
-    fn from_u16(n: u16) -> BNodeType {
+    //The type comes straight from page data, so an unknown value means the page is corrupted
+    fn from_u16(n: u16) -> Result<BNodeType> {
         match n {
-            1 => BNodeType::InternalNode,
-            2 => BNodeType::LeafNode,
-            _ => unreachable!("Invalid value for BNodeType: {}", n),
+            1 => Ok(BNodeType::InternalNode),
+            2 => Ok(BNodeType::LeafNode),
+            _ => Err(DbError::corruption(format!(
+                "Invalid value for BNodeType: {}",
+                n
+            ))),
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            BNodeType::InternalNode => 1,
+            BNodeType::LeafNode => 2,
         }
     }
 }
 
-struct BNode {
+#[derive(Clone)]
+pub struct BNode {
     /*raw data
     format:
     | type | n_keys |   pointers   |   offsets   | k-v pairs |
@@ -34,14 +77,132 @@ struct BNode {
     k-v pair format:
     | k_len | v_len | key | val |
     |   2B  |   2B  | ... | ... |
+
+    Pointers of leaf nodes are 0, except for values stored in overflow pages: then the
+    pointer is the first page of the chain and val is the 8B length of the whole value.
+    Overflow pages are stored as BNodes too, with their own format (see overflow.rs)
     */
-    data: [u8; BTREE_PAGE_SIZE as usize],
+    data: Box<[u8]>,
 }
 
 impl BNode {
+    //Create an empty node, the header has to be set before it is used
+    fn new(page_size: usize) -> BNode {
+        BNode {
+            data: vec![0; page_size].into_boxed_slice(),
+        }
+    }
+
+    //Decode a page read from storage. This is the only way raw bytes become a BNode,
+    //so everything the accessors rely on is validated here: the type is known, the pointers
+    //and offsets fit in the page, every offset points right past the previous kv pair,
+    //kv pairs stay within the page and size limits, and keys are in ascending order.
+    //The node owns a copy of the page even when it is read from the memory mapping: a node
+    //borrowing the mapping would tie every node and the page cache to the pager's lifetime,
+    //which is left for when the copy shows up in profiles
+    pub(crate) fn parse(page: &[u8]) -> Result<BNode> {
+        if !valid_page_size(page.len()) {
+            return Err(DbError::corruption(format!(
+                "page is {} bytes, which is not a valid page size",
+                page.len()
+            )));
+        }
+        let node = BNode { data: page.into() };
+        if node.read_u16(0)? == OVERFLOW_TYPE {
+            overflow::decode_page(&node.data)?;
+            return Ok(node);
+        }
+
+        let b_type = node.b_type()?;
+        let n_keys = node.n_keys();
+        if b_type == BNodeType::InternalNode && n_keys == 0 {
+            return Err(DbError::corruption("internal node without children"));
+        }
+        if node.kv_start() > node.page_size() - CHECKSUM_SIZE {
+            return Err(DbError::corruption(format!(
+                "{} keys don't fit in a page",
+                n_keys
+            )));
+        }
+
+        let mut offset = 0;
+        for idx in 0..n_keys {
+            let position = node.kv_start() + offset;
+            let key_length = node.read_u16(position)?;
+            let value_length = node.read_u16(position + 2)?;
+            if key_length > BTREE_MAX_KEY_SIZE || value_length > BTREE_MAX_VAL_SIZE {
+                return Err(DbError::corruption(format!(
+                    "kv pair {} has key length {} and value length {}",
+                    idx, key_length, value_length
+                )));
+            }
+
+            let next_offset = node.get_offset(idx + 1)? as usize;
+            let expected_offset = offset + 4 + key_length as usize + value_length as usize;
+            if next_offset != expected_offset {
+                return Err(DbError::corruption(format!(
+                    "offset {} is {} but kv pair {} ends at {}",
+                    idx + 1,
+                    next_offset,
+                    idx,
+                    expected_offset
+                )));
+            }
+            offset = next_offset;
+            if node.kv_start() + offset > node.page_size() - CHECKSUM_SIZE {
+                return Err(DbError::corruption(format!(
+                    "kv pair {} ends at {}, past the page checksum",
+                    idx,
+                    node.kv_start() + offset
+                )));
+            }
+
+            //Reading the value also checks that the pair ends inside the page
+            node.get_value(idx)?;
+            if b_type == BNodeType::LeafNode && node.get_ptr(idx)? != 0 && value_length != 8 {
+                return Err(DbError::corruption(format!(
+                    "kv pair {} points to overflow pages but its value is {} bytes instead of a length",
+                    idx, value_length
+                )));
+            }
+            if idx > 0 && compare_keys(node.get_key(idx - 1)?, node.get_key(idx)?).is_ge() {
+                return Err(DbError::corruption(format!(
+                    "key {} is not greater than the previous key",
+                    idx
+                )));
+            }
+        }
+
+        Ok(node)
+    }
+
+    //Create an overflow page holding payload, a part of a large value
+    fn overflow(page_size: usize, next: u64, payload: &[u8]) -> BNode {
+        let node = BNode {
+            data: overflow::encode_page(page_size, next, payload).into_boxed_slice(),
+        };
+        #[cfg(feature = "check-invariants")]
+        node.check_invariants();
+        node
+    }
+
+    //Next page pointer and payload of an overflow page
+    fn overflow_page(&self) -> Result<(u64, &[u8])> {
+        overflow::decode_page(&self.data)
+    }
+
+    //Raw page bytes, as they are written to storage
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        self.data.len()
+    }
+
     //Return the type of current node
-    fn b_type(&self) -> BNodeType {
-        BNodeType::from_u16(u16::from_le_bytes(self.data[0..2].try_into().unwrap()))
+    fn b_type(&self) -> Result<BNodeType> {
+        BNodeType::from_u16(self.read_u16(0)?)
     }
 
     //Returns the number of keys in current node
@@ -65,55 +226,63 @@ impl BNode {
         self.data[2..4].copy_from_slice(&bytes);
     }
 
+    //Read bytes at position, failing instead of panicking when n_keys or stored
+    //lengths point past the end of the page
+    fn read_bytes(&self, position: usize, length: usize) -> Result<&[u8]> {
+        self.data.get(position..position + length).ok_or_else(|| {
+            DbError::corruption(format!(
+                "{} bytes at position {} are past the end of the page",
+                length, position
+            ))
+        })
+    }
+
+    fn read_u16(&self, position: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(
+            self.read_bytes(position, 2)?.try_into().unwrap(),
+        ))
+    }
+
     //Return the pointer for a child node corresponding to index idx
-    fn get_ptr(&self, idx: u16) -> u64 {
-        assert!(idx < self.n_keys());
+    fn get_ptr(&self, idx: u16) -> Result<u64> {
+        debug_assert!(idx < self.n_keys());
 
         //Pointer positions start from offset of fixed size HEADER and are 8 bytes long
-        let position: u16 = (HEADER) as u16 + 8 * idx;
+        let position = HEADER as usize + 8 * idx as usize;
 
-        u64::from_le_bytes(
-            self.data[position as usize..(position + 8) as usize]
-                .try_into()
-                .unwrap(),
-        )
+        Ok(u64::from_le_bytes(
+            self.read_bytes(position, 8)?.try_into().unwrap(),
+        ))
     }
 
     //Set pointer of child node referenced by idx
     fn set_ptr(&mut self, idx: u16, value: u64) {
-        assert!(idx < self.n_keys());
+        debug_assert!(idx < self.n_keys());
 
         //Pointer positions start from offset of fixed size HEADER and are 8 bytes long
-        let position: u16 = (HEADER) as u16 + 8 * idx;
+        let position = HEADER as usize + 8 * idx as usize;
 
-        self.data[position as usize..(position + 8) as usize]
-            .copy_from_slice(value.to_le_bytes().as_slice());
+        self.data[position..position + 8].copy_from_slice(value.to_le_bytes().as_slice());
     }
 
     //Get the offset position for the key in data array based on key idx
-    fn offset_position(&self, idx: u16) -> u16 {
-        assert!(1 < idx && idx < self.n_keys());
+    //Offsets are stored for keys 1..=n_keys, the one for n_keys marks the end of the last kv pair
+    fn offset_position(&self, idx: u16) -> usize {
+        debug_assert!(1 <= idx && idx <= self.n_keys());
 
         //Offset positions start after fixed header and pointers to the children
         //(idx - 1) is necessary since we do not explicitly store offset for the first key
-        HEADER as u16 + 8 * self.n_keys() + 2 * (idx - 1)
+        HEADER as usize + 8 * self.n_keys() as usize + 2 * (idx as usize - 1)
     }
 
     //Get the key position in the data array based on offset
-    fn get_offset(&self, idx: u16) -> u16 {
+    fn get_offset(&self, idx: u16) -> Result<u16> {
         if idx == 0 {
-            return 0;
+            return Ok(0);
         }
 
-        //Locate the offset position in data array
-        let offset_position = self.offset_position(idx);
-
-        //Use the position to return the actual offset value
-        u16::from_le_bytes(
-            self.data[offset_position as usize..(offset_position + 2) as usize]
-                .try_into()
-                .unwrap(),
-        )
+        //Locate the offset position in data array and read the actual offset value
+        self.read_u16(self.offset_position(idx))
     }
 
     //Set the offset for a key at the offset position for idx
@@ -122,70 +291,978 @@ impl BNode {
         let offset_position = self.offset_position(idx);
 
         //Set the value at the located offset position
-        self.data[offset_position as usize..(offset_position + 2) as usize]
+        self.data[offset_position..offset_position + 2]
             .copy_from_slice(value.to_le_bytes().as_slice());
     }
 
+    //Position in the data array where the kv pairs start
+    fn kv_start(&self) -> usize {
+        //Data starts for an offset of fixed Header + number of child pointers + number of key offsets
+        HEADER as usize + 8 * self.n_keys() as usize + 2 * self.n_keys() as usize
+    }
+
     //Get the position of kv pair in the data array
-    fn get_kv_pair_position(&self, idx: u16) -> u16 {
-        assert!(idx < self.n_keys());
+    fn get_kv_pair_position(&self, idx: u16) -> Result<usize> {
+        debug_assert!(idx <= self.n_keys());
 
-        //Data starts for an offset of fixed Header + number of child pointers + number of key offsets
-        HEADER as u16 + 8 * self.n_keys() + 2 * self.n_keys() + self.get_offset(idx)
+        Ok(self.kv_start() + self.get_offset(idx)? as usize)
     }
 
     //Get the pointer to data located at the key position
-    fn get_key(&self, idx: u16) -> &[u8] {
-        assert!(idx < self.n_keys());
+    fn get_key(&self, idx: u16) -> Result<&[u8]> {
+        debug_assert!(idx < self.n_keys());
 
         //Get the position of kv pair in array
-        let position: u16 = self.get_kv_pair_position(idx);
+        let position = self.get_kv_pair_position(idx)?;
 
         //Key length is stored in first two bytes of key data
-        let key_length = u16::from_le_bytes(
-            self.data[position as usize..(position + 2) as usize]
-                .try_into()
-                .unwrap(),
-        );
+        let key_length = self.read_u16(position)? as usize;
+
         //Skip first 4 bytes key length and value length and return key length amount of bytes
-        self.data[(position + 4) as usize..(position + 4 + key_length) as usize]
-            .try_into()
-            .unwrap()
+        self.read_bytes(position + 4, key_length)
     }
 
     //Get value for key which resides at index idx
-    fn get_value(&self, idx: u16) -> &[u8] {
-        assert!(idx < self.n_keys());
+    fn get_value(&self, idx: u16) -> Result<&[u8]> {
+        debug_assert!(idx < self.n_keys());
 
         //Get the position of kv pair in array
-        let position: u16 = self.get_kv_pair_position(idx);
+        let position = self.get_kv_pair_position(idx)?;
 
         //Key length is stored in first two bytes of kv data
-        let key_length = u16::from_le_bytes(
-            self.data[position as usize..(position + 2) as usize]
-                .try_into()
-                .unwrap(),
-        );
+        let key_length = self.read_u16(position)? as usize;
         //Key length is stored in 3rd and 4th bytes of kv data
-        let value_length = u16::from_le_bytes(
-            self.data[(position + 2) as usize..(position + 4) as usize]
-                .try_into()
-                .unwrap(),
-        );
+        let value_length = self.read_u16(position + 2)? as usize;
 
         let position_of_value_data = position + 4 + key_length;
 
-        self.data[position_of_value_data as usize..(position_of_value_data + value_length) as usize]
-            .try_into()
-            .unwrap()
+        self.read_bytes(position_of_value_data, value_length)
     }
 
-    fn num_used_bytes(&self) -> u16 {
+    fn num_used_bytes(&self) -> Result<usize> {
         //Return the offset from the start of array to the end of last kv pair
         self.get_kv_pair_position(self.n_keys())
     }
+
+    //Revalidate a freshly built node with the same checks applied to pages read from disk,
+    //so a layout bug panics where the node is made instead of surfacing as corruption later
+    #[cfg(feature = "check-invariants")]
+    fn check_invariants(&self) {
+        if let Err(err) = BNode::parse(&self.data) {
+            panic!(
+                "built node breaks its invariants: {}\n{}",
+                err,
+                self.annotated_hexdump()
+            );
+        }
+    }
+
+    //Dump the raw page as hex with every field labeled, for diagnosing layout bugs.
+    //Lengths are read straight from the bytes and clamped to the page, so this also works
+    //on nodes whose header or offsets are broken
+    #[cfg(feature = "check-invariants")]
+    fn annotated_hexdump(&self) -> String {
+        let mut out = String::new();
+        let data = &self.data[..];
+        let n_keys = self.n_keys() as usize;
+        let read_u16 = |position: usize| match data.get(position..position + 2) {
+            Some(bytes) => u16::from_le_bytes(bytes.try_into().unwrap()) as usize,
+            None => 0,
+        };
+
+        let type_label = match read_u16(0) {
+            1 => "type = InternalNode".to_string(),
+            2 => "type = LeafNode".to_string(),
+            4 => "type = Overflow".to_string(),
+            other => format!("type = {} (invalid)", other),
+        };
+        hexdump_field(&mut out, data, 0, 2, &type_label);
+
+        if read_u16(0) == OVERFLOW_TYPE as usize {
+            let size = read_u16(2).min(overflow::capacity(data.len()));
+            let next = u64::from_le_bytes(data[4..12].try_into().unwrap());
+            hexdump_field(&mut out, data, 2, 4, &format!("size = {}", read_u16(2)));
+            hexdump_field(&mut out, data, 4, 12, &format!("next = {}", next));
+            hexdump_field(&mut out, data, 12, 12 + size, "payload");
+            hexdump_field(&mut out, data, 12 + size, data.len(), "free");
+            return out;
+        }
+        hexdump_field(&mut out, data, 2, 4, &format!("n_keys = {}", n_keys));
+
+        let mut position = HEADER as usize;
+        for idx in 0..n_keys {
+            let label = match data.get(position..position + 8) {
+                Some(bytes) => format!(
+                    "ptr[{}] = {}",
+                    idx,
+                    u64::from_le_bytes(bytes.try_into().unwrap())
+                ),
+                None => format!("ptr[{}]", idx),
+            };
+            hexdump_field(&mut out, data, position, position + 8, &label);
+            position += 8;
+        }
+        for idx in 1..=n_keys {
+            let label = format!("offset[{}] = {}", idx, read_u16(position));
+            hexdump_field(&mut out, data, position, position + 2, &label);
+            position += 2;
+        }
+        for idx in 0..n_keys {
+            if position >= data.len() {
+                break;
+            }
+            let key_length = read_u16(position);
+            let value_length = read_u16(position + 2);
+            let label = format!(
+                "kv[{}] k_len = {} v_len = {}",
+                idx, key_length, value_length
+            );
+            hexdump_field(&mut out, data, position, position + 4, &label);
+            hexdump_field(
+                &mut out,
+                data,
+                position + 4,
+                position + 4 + key_length,
+                &format!("kv[{}] key", idx),
+            );
+            position += 4 + key_length;
+            hexdump_field(
+                &mut out,
+                data,
+                position,
+                position + value_length,
+                &format!("kv[{}] val", idx),
+            );
+            position += value_length;
+        }
+        hexdump_field(&mut out, data, position, data.len(), "free");
+        out
+    }
+
+    //Concatenate the entries of two sibling nodes into one node
+    fn merge(left: &BNode, right: &BNode) -> Result<BNode> {
+        let mut merged = NodeBuilder::new(left.b_type()?, left.page_size());
+        append_range(&mut merged, left, 0, left.n_keys())?;
+        append_range(&mut merged, right, 0, right.n_keys())?;
+        Ok(merged.build())
+    }
+
+    //Return the entries of this internal node with count links starting at idx
+    //replaced by the given (pointer, first key) links
+    fn replace_links(&self, idx: u16, count: u16, links: &[(u64, Vec<u8>)]) -> Result<NodeBuilder> {
+        let mut updated = NodeBuilder::new(BNodeType::InternalNode, self.page_size());
+        append_range(&mut updated, self, 0, idx)?;
+        updated.push_links(links);
+        append_range(&mut updated, self, idx + count, self.n_keys())?;
+        Ok(updated)
+    }
+
+    //Return the index of the last key that is less than or equal to key, found with a
+    //binary search over the sorted keys. The first key of every node is less than or equal
+    //to any key looked up in it (leaves start with an empty sentinel key), so 0 is returned
+    //when nothing else matches
+    fn lookup_le(&self, key: &[u8]) -> Result<u16> {
+        //Keys before lo are <= key, keys from hi onwards are > key
+        let mut lo = 1;
+        let mut hi = self.n_keys();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match compare_keys(self.get_key(mid)?, key) {
+                Ordering::Greater => hi = mid,
+                _ => lo = mid + 1,
+            }
+        }
+        Ok(lo.saturating_sub(1))
+    }
+
+    //Write kv pair at position, the caller is responsible for recording its offset
+    fn set_kv(&mut self, position: usize, key: &[u8], val: &[u8]) {
+        let key_length = key.len() as u16;
+        let value_length = val.len() as u16;
+
+        self.data[position..position + 2].copy_from_slice(&key_length.to_le_bytes());
+        self.data[position + 2..position + 4].copy_from_slice(&value_length.to_le_bytes());
+        self.data[position + 4..position + 4 + key.len()].copy_from_slice(key);
+        self.data[position + 4 + key.len()..position + 4 + key.len() + val.len()]
+            .copy_from_slice(val);
+    }
+}
+
+//Append data[start..end] to out as rows of 16 hex bytes, with the label on the first row.
+//The range is clamped to the data so broken lengths can't run past the page
+#[cfg(feature = "check-invariants")]
+fn hexdump_field(out: &mut String, data: &[u8], start: usize, end: usize, label: &str) {
+    let start = start.min(data.len());
+    let end = end.min(data.len());
+    if start == end {
+        return;
+    }
+
+    for (row, chunk) in data[start..end].chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let row_label = if row == 0 { label } else { "" };
+        let line = format!(
+            "{:04x}  {:<47}  {}",
+            start + row * 16,
+            hex.join(" "),
+            row_label
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+}
+
+//Size of a node holding n_keys entries whose keys and values take kv_bytes in total
+fn node_size(n_keys: usize, kv_bytes: usize) -> usize {
+    HEADER as usize + 8 * n_keys + 2 * n_keys + 4 * n_keys + kv_bytes
+}
+
+//Accumulates the entries of a node in key order and lays them out as a BNode,
+//so tree operations never have to compute header, pointer or offset positions by hand
+struct NodeBuilder {
+    b_type: BNodeType,
+    //Size of the page the node is built in
+    page_size: usize,
+    //(child pointer, key, value) of every entry, pointers are 0 in leaf nodes
+    entries: Vec<(u64, Vec<u8>, Vec<u8>)>,
+    //Total length of all keys and values
+    kv_bytes: usize,
+}
+
+impl NodeBuilder {
+    fn new(b_type: BNodeType, page_size: usize) -> NodeBuilder {
+        NodeBuilder {
+            b_type,
+            page_size,
+            entries: Vec::new(),
+            kv_bytes: 0,
+        }
+    }
+
+    //Append an entry, entries have to be pushed in ascending key order
+    fn push(&mut self, ptr: u64, key: &[u8], val: &[u8]) -> &mut NodeBuilder {
+        assert!(key.len() <= BTREE_MAX_KEY_SIZE as usize);
+        assert!(val.len() <= BTREE_MAX_VAL_SIZE as usize);
+
+        self.kv_bytes += key.len() + val.len();
+        self.entries.push((ptr, key.to_vec(), val.to_vec()));
+        self
+    }
+
+    //Append (pointer, first key) links to child nodes of an internal node
+    fn push_links(&mut self, links: &[(u64, Vec<u8>)]) -> &mut NodeBuilder {
+        for (ptr, key) in links {
+            self.push(*ptr, key, &[]);
+        }
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    //Move the entries from at onwards into a new builder of the same type
+    fn split_off(&mut self, at: usize) -> NodeBuilder {
+        let entries = self.entries.split_off(at);
+        let kv_bytes: usize = entries
+            .iter()
+            .map(|(_, key, val)| key.len() + val.len())
+            .sum();
+        self.kv_bytes -= kv_bytes;
+
+        NodeBuilder {
+            b_type: self.b_type,
+            page_size: self.page_size,
+            entries,
+            kv_bytes,
+        }
+    }
+
+    //Split the entries in two so that the right half fits in a page.
+    //The split starts in the middle and moves so that the left half fits too if possible,
+    //when it doesn't the caller has to split it again
+    fn split_in_two(mut self) -> (NodeBuilder, NodeBuilder) {
+        let sizes: Vec<usize> = self
+            .entries
+            .iter()
+            .map(|(_, key, val)| key.len() + val.len())
+            .collect();
+        let bytes_before = |at: usize| node_size(at, sizes[..at].iter().sum());
+        let bytes_after = |at: usize| node_size(sizes.len() - at, sizes[at..].iter().sum());
+
+        let mut at = sizes.len() / 2;
+        while at > 1 && bytes_before(at) > self.capacity() {
+            at -= 1;
+        }
+        while bytes_after(at) > self.capacity() {
+            at += 1;
+        }
+
+        let right = self.split_off(at);
+        (self, right)
+    }
+
+    //Build one node, or split into 2 or 3 nodes when the entries don't fit in a page.
+    //Three are always enough since a single kv pair is at most a page minus the header
+    fn build_split(self) -> Vec<BNode> {
+        if self.fits() {
+            return vec![self.build()];
+        }
+
+        let (left, right) = self.split_in_two();
+        if left.fits() {
+            return vec![left.build(), right.build()];
+        }
+
+        let (left, middle) = left.split_in_two();
+        vec![left.build(), middle.build(), right.build()]
+    }
+
+    //Number of bytes the node would take if it was built now
+    fn size(&self) -> usize {
+        node_size(self.entries.len(), self.kv_bytes)
+    }
+
+    //Bytes of a page the node can use
+    fn capacity(&self) -> usize {
+        self.page_size - CHECKSUM_SIZE
+    }
+
+    //Check whether the entries fit in a single page
+    fn fits(&self) -> bool {
+        self.size() <= self.capacity()
+    }
+
+    //Lay out the accumulated entries in a new node
+    fn build(&self) -> BNode {
+        assert!(
+            self.fits(),
+            "node of {} bytes exceeds page size",
+            self.size()
+        );
+
+        let mut node = BNode::new(self.page_size);
+        node.set_header(self.b_type.to_u16(), self.entries.len() as u16);
+
+        let kv_start = node.kv_start();
+        let mut offset = 0;
+        for (idx, (ptr, key, val)) in self.entries.iter().enumerate() {
+            node.set_ptr(idx as u16, *ptr);
+            node.set_kv(kv_start + offset, key, val);
+
+            //Offset of the next pair is the current offset plus the size of this pair
+            offset += 4 + key.len() + val.len();
+            node.set_offset(idx as u16 + 1, offset as u16);
+        }
+        #[cfg(feature = "check-invariants")]
+        node.check_invariants();
+        node
+    }
 }
 
-pub struct BTree {
+pub struct BTree<T: Tree> {
+    //Pointer to the root node, 0 while the tree is empty
     root: u64,
+    //Encoding applied to user keys before they are stored or looked up
+    key_encoding: KeyEncoding,
+    pages: T,
+}
+
+impl<T: Tree> BTree<T> {
+    pub fn new(root: u64, key_encoding: KeyEncoding, pages: T) -> BTree<T> {
+        BTree {
+            root,
+            key_encoding,
+            pages,
+        }
+    }
+
+    pub fn key_encoding(&self) -> KeyEncoding {
+        self.key_encoding
+    }
+
+    //Pointer to the root node, callers persist it to reopen the tree later
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    //Point the tree at another root, e.g. the last persisted one when updates are undone
+    pub fn set_root(&mut self, root: u64) {
+        self.root = root;
+    }
+
+    pub fn pages(&self) -> &T {
+        &self.pages
+    }
+
+    pub fn pages_mut(&mut self) -> &mut T {
+        &mut self.pages
+    }
+
+    //Return the key in the form it is stored in the tree
+    fn normalize_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        self.key_encoding.encode(key)
+    }
+
+    //Insert a key or replace the value of an existing one.
+    //Nodes are never modified in place: every node on the path to the leaf is rebuilt,
+    //split into up to 3 nodes if it outgrew a page, and stored as a new page.
+    //Values larger than BTREE_MAX_VAL_SIZE are written to a chain of overflow pages
+    pub fn insert(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        let key = self.normalize_key(key);
+        if key.is_empty() {
+            return Err(DbError::EmptyKey);
+        }
+        if key.len() > BTREE_MAX_KEY_SIZE as usize {
+            return Err(DbError::KeyTooLarge {
+                length: key.len(),
+                max: BTREE_MAX_KEY_SIZE as usize,
+            });
+        }
+
+        let (ptr, val) = if val.len() > BTREE_MAX_VAL_SIZE as usize {
+            let length = (val.len() as u64).to_le_bytes();
+            (self.write_overflow(val), Cow::Owned(length.to_vec()))
+        } else {
+            (0, Cow::Borrowed(val))
+        };
+
+        if self.root == 0 {
+            //The first leaf starts with an empty sentinel key, so every key has
+            //a smaller or equal key to the left of it in every node on its path
+            let mut root = NodeBuilder::new(BNodeType::LeafNode, self.pages.page_size());
+            root.push(0, &[], &[]).push(ptr, &key, &val);
+            self.root = self.pages.new(root.build());
+            return Ok(());
+        }
+
+        let node = self.pages.get(self.root)?;
+        let updated = self.tree_insert(&node, &key, ptr, &val)?;
+        self.pages.del(self.root);
+        self.replace_root(updated)
+    }
+
+    //Store the updated root node. A root which doesn't fit in a page gets split and a new
+    //level is added on top, an internal root left with a single link is replaced by its child
+    fn replace_root(&mut self, updated: NodeBuilder) -> Result<()> {
+        if updated.b_type == BNodeType::InternalNode && updated.len() == 1 {
+            self.root = updated.entries[0].0;
+            return Ok(());
+        }
+
+        let mut split = updated.build_split();
+        if split.len() == 1 {
+            self.root = self.pages.new(split.remove(0));
+        } else {
+            let mut root = NodeBuilder::new(BNodeType::InternalNode, self.pages.page_size());
+            root.push_links(&self.store_children(split)?);
+            self.root = self.pages.new(root.build());
+        }
+        Ok(())
+    }
+
+    //Store new child nodes and return the (pointer, first key) links to them
+    fn store_children(&mut self, children: Vec<BNode>) -> Result<Vec<(u64, Vec<u8>)>> {
+        let mut links = Vec::with_capacity(children.len());
+        for child in children {
+            let first_key = child.get_key(0)?.to_vec();
+            links.push((self.pages.new(child), first_key));
+        }
+        Ok(links)
+    }
+
+    //Insert the kv pair into the subtree rooted at node and return the entries of the
+    //updated node. They may not fit in a single page, the caller splits them.
+    //ptr is the first overflow page of the value, 0 when val is stored inline
+    fn tree_insert(
+        &mut self,
+        node: &BNode,
+        key: &[u8],
+        ptr: u64,
+        val: &[u8],
+    ) -> Result<NodeBuilder> {
+        let idx = node.lookup_le(key)?;
+
+        match node.b_type()? {
+            BNodeType::LeafNode => {
+                let mut updated = NodeBuilder::new(BNodeType::LeafNode, node.page_size());
+                if node.get_key(idx)? == key {
+                    //Key exists, replace its value
+                    self.free_overflow(node.get_ptr(idx)?)?;
+                    append_range(&mut updated, node, 0, idx)?;
+                    updated.push(ptr, key, val);
+                    append_range(&mut updated, node, idx + 1, node.n_keys())?;
+                } else {
+                    //New key goes right after the last smaller key
+                    append_range(&mut updated, node, 0, idx + 1)?;
+                    updated.push(ptr, key, val);
+                    append_range(&mut updated, node, idx + 1, node.n_keys())?;
+                }
+                Ok(updated)
+            }
+            BNodeType::InternalNode => {
+                let child_ptr = node.get_ptr(idx)?;
+                let child = self.pages.get(child_ptr)?;
+                let updated_child = self.tree_insert(&child, key, ptr, val)?;
+                self.pages.del(child_ptr);
+
+                //Replace the link to the child with links to the nodes it was split into
+                let links = self.store_children(updated_child.build_split())?;
+                node.replace_links(idx, 1, &links)
+            }
+        }
+    }
+
+    //Look up the value stored for key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.normalize_key(key);
+        if self.root == 0 || key.is_empty() {
+            return Ok(None);
+        }
+
+        let mut node = self.pages.get(self.root)?;
+        loop {
+            let idx = node.lookup_le(&key)?;
+            match node.b_type()? {
+                BNodeType::LeafNode => {
+                    if node.get_key(idx)? == &key[..] {
+                        return Ok(Some(self.read_value(&node, idx)?));
+                    }
+                    return Ok(None);
+                }
+                BNodeType::InternalNode => node = self.pages.get(node.get_ptr(idx)?)?,
+            }
+        }
+    }
+
+    //Value of the kv pair at idx in a leaf, reassembled from its overflow pages if needed
+    fn read_value(&self, leaf: &BNode, idx: u16) -> Result<Vec<u8>> {
+        let head = leaf.get_ptr(idx)?;
+        let val = leaf.get_value(idx)?;
+        if head == 0 {
+            return Ok(val.to_vec());
+        }
+
+        //The length was checked to be 8 bytes when the leaf was parsed
+        let length = u64::from_le_bytes(val.try_into().unwrap());
+        let mut value = Vec::new();
+        let mut ptr = head;
+        while ptr != 0 {
+            let page = self.pages.get(ptr)?;
+            let (next, payload) = page.overflow_page().map_err(|err| err.with_page(ptr))?;
+            if (value.len() + payload.len()) as u64 > length {
+                return Err(DbError::corruption(format!(
+                    "overflow chain starting at page {} holds more than the {} byte value",
+                    head, length
+                ))
+                .with_page(ptr));
+            }
+            value.extend_from_slice(payload);
+            ptr = next;
+        }
+
+        if value.len() as u64 != length {
+            return Err(DbError::corruption(format!(
+                "overflow chain starting at page {} holds {} bytes of the {} byte value",
+                head,
+                value.len(),
+                length
+            )));
+        }
+        Ok(value)
+    }
+
+    //Store a large value in a chain of overflow pages and return the first page.
+    //The chain is written back to front so every page already knows the next one
+    fn write_overflow(&mut self, val: &[u8]) -> u64 {
+        let page_size = self.pages.page_size();
+        let mut next = 0;
+        for chunk in val.chunks(overflow::capacity(page_size)).rev() {
+            next = self.pages.new(BNode::overflow(page_size, next, chunk));
+        }
+        next
+    }
+
+    //Release the overflow pages of a value which is replaced or deleted, head is the
+    //pointer of its kv pair so nothing happens for inline values
+    fn free_overflow(&mut self, head: u64) -> Result<()> {
+        let mut ptr = head;
+        while ptr != 0 {
+            let (next, _) = self
+                .pages
+                .get(ptr)?
+                .overflow_page()
+                .map_err(|err| err.with_page(ptr))?;
+            self.pages.del(ptr);
+            ptr = next;
+        }
+        Ok(())
+    }
+
+    //Iterate over the kv pairs with keys between start and end in key order.
+    //Bounds are given as user keys, the yielded keys are the stored (encoded) ones
+    pub fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<BTreeIter<'_, T>> {
+        let mut iter = BTreeIter {
+            tree: self,
+            path: Vec::new(),
+            direction: Direction::Forward,
+            stop: end.map(|key| self.normalize_key(key).into_owned()),
+        };
+        if self.root == 0 {
+            return Ok(iter);
+        }
+
+        let start = start.map(|key| self.normalize_key(key));
+        let seek_key: &[u8] = match &start {
+            Bound::Included(key) | Bound::Excluded(key) => key,
+            Bound::Unbounded => &[],
+        };
+
+        //Descend to the last key <= the start key, the sentinel makes sure there is one
+        iter.seek(|node| node.lookup_le(seek_key))?;
+
+        //That key is only part of the range when it is exactly an included start
+        let (leaf, idx) = iter.path.last().unwrap();
+        let before_start = match &start {
+            Bound::Included(key) => compare_keys(leaf.get_key(*idx)?, key) == Ordering::Less,
+            Bound::Excluded(key) => compare_keys(leaf.get_key(*idx)?, key) != Ordering::Greater,
+            Bound::Unbounded => false,
+        };
+        if before_start {
+            iter.advance()?;
+        }
+        Ok(iter)
+    }
+
+    //Same range as scan, but the kv pairs are yielded in descending key order starting
+    //from end, so the last keys before some key are found without walking the whole range
+    pub fn scan_rev(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<BTreeIter<'_, T>> {
+        let mut iter = BTreeIter {
+            tree: self,
+            path: Vec::new(),
+            direction: Direction::Backward,
+            stop: start.map(|key| self.normalize_key(key).into_owned()),
+        };
+        if self.root == 0 {
+            return Ok(iter);
+        }
+
+        //Descend to the last key <= the end key, or to the last key of the tree without one
+        let end = end.map(|key| self.normalize_key(key));
+        iter.seek(|node| match &end {
+            Bound::Included(key) | Bound::Excluded(key) => node.lookup_le(key),
+            Bound::Unbounded => Ok(node.n_keys().saturating_sub(1)),
+        })?;
+
+        //Only an excluded end has to be stepped over
+        let (leaf, idx) = iter.path.last().unwrap();
+        let past_end = match &end {
+            Bound::Excluded(key) => leaf.get_key(*idx)? == &key[..],
+            _ => false,
+        };
+        if past_end {
+            iter.retreat()?;
+        }
+        Ok(iter)
+    }
+
+    //Delete a key, returns whether it was present.
+    //Like insert, every node on the path is rebuilt as a new page. Nodes which become
+    //smaller than a quarter of a page are merged with a sibling, and the root is replaced
+    //by its only child whenever it is left with a single link
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
+        let key = self.normalize_key(key);
+        //The empty key is the sentinel, it is never visible to users
+        if self.root == 0 || key.is_empty() {
+            return Ok(false);
+        }
+
+        let node = self.pages.get(self.root)?;
+        let Some(updated) = self.tree_delete(&node, &key)? else {
+            return Ok(false);
+        };
+        self.pages.del(self.root);
+        self.replace_root(updated)?;
+        Ok(true)
+    }
+
+    //Delete the key from the subtree rooted at node and return the entries of the updated
+    //node, or None when the key isn't there. The node may end up empty or underfull, in which
+    //case the caller merges it with a sibling. It can also outgrow a page: when the first key
+    //of a child is deleted the link to it takes the next, possibly longer, key
+    fn tree_delete(&mut self, node: &BNode, key: &[u8]) -> Result<Option<NodeBuilder>> {
+        let idx = node.lookup_le(key)?;
+
+        match node.b_type()? {
+            BNodeType::LeafNode => {
+                if node.get_key(idx)? != key {
+                    return Ok(None);
+                }
+                self.free_overflow(node.get_ptr(idx)?)?;
+
+                let mut updated = NodeBuilder::new(BNodeType::LeafNode, node.page_size());
+                append_range(&mut updated, node, 0, idx)?;
+                append_range(&mut updated, node, idx + 1, node.n_keys())?;
+                Ok(Some(updated))
+            }
+            BNodeType::InternalNode => {
+                let child_ptr = node.get_ptr(idx)?;
+                let child = self.pages.get(child_ptr)?;
+                let Some(updated_child) = self.tree_delete(&child, key)? else {
+                    return Ok(None);
+                };
+                self.pages.del(child_ptr);
+
+                if !updated_child.fits() {
+                    let links = self.store_children(updated_child.build_split())?;
+                    return Ok(Some(node.replace_links(idx, 1, &links)?));
+                }
+
+                let updated_child = updated_child.build();
+                let updated = match self.merge_direction(node, idx, &updated_child)? {
+                    MergeDirection::Left(left_ptr) => {
+                        let left = self.pages.get(left_ptr)?;
+                        let merged = BNode::merge(&left, &updated_child)?;
+                        self.pages.del(left_ptr);
+                        let links = self.store_children(vec![merged])?;
+                        node.replace_links(idx - 1, 2, &links)?
+                    }
+                    MergeDirection::Right(right_ptr) => {
+                        let right = self.pages.get(right_ptr)?;
+                        let merged = BNode::merge(&updated_child, &right)?;
+                        self.pages.del(right_ptr);
+                        let links = self.store_children(vec![merged])?;
+                        node.replace_links(idx, 2, &links)?
+                    }
+                    MergeDirection::None if updated_child.n_keys() == 0 => {
+                        //The child was the only one and is now empty, so this node is empty too
+                        debug_assert!(node.n_keys() == 1 && idx == 0);
+                        node.replace_links(idx, 1, &[])?
+                    }
+                    MergeDirection::None => {
+                        let links = self.store_children(vec![updated_child])?;
+                        node.replace_links(idx, 1, &links)?
+                    }
+                };
+                Ok(Some(updated))
+            }
+        }
+    }
+
+    //Decide whether the updated child at idx should be merged with one of its siblings
+    fn merge_direction(&self, node: &BNode, idx: u16, updated: &BNode) -> Result<MergeDirection> {
+        let updated_size = updated.num_used_bytes()?;
+        if updated_size > updated.page_size() / 4 {
+            return Ok(MergeDirection::None);
+        }
+
+        //Merged node keeps a single header, so it fits when both bodies fit in one page
+        let fits = |sibling: &BNode| -> Result<bool> {
+            Ok(sibling.num_used_bytes()? + updated_size - HEADER as usize
+                <= updated.page_size() - CHECKSUM_SIZE)
+        };
+
+        if idx > 0 {
+            let left_ptr = node.get_ptr(idx - 1)?;
+            if fits(&self.pages.get(left_ptr)?)? {
+                return Ok(MergeDirection::Left(left_ptr));
+            }
+        }
+        if idx + 1 < node.n_keys() {
+            let right_ptr = node.get_ptr(idx + 1)?;
+            if fits(&self.pages.get(right_ptr)?)? {
+                return Ok(MergeDirection::Right(right_ptr));
+            }
+        }
+        Ok(MergeDirection::None)
+    }
+}
+
+//Order in which a range of a tree is visited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+//Cursor over a range of a tree, created by BTree::scan or BTree::scan_rev.
+//It only holds the nodes on the path from the root to the current leaf, the position in
+//each of them is the index of the link followed (or of the current kv pair in the leaf)
+pub struct BTreeIter<'a, T: Tree> {
+    tree: &'a BTree<T>,
+    //(node, index) from the root down to the current leaf, empty once the scan is over
+    path: Vec<(BNode, u16)>,
+    direction: Direction,
+    //Stored form of the bound the scan moves towards, the end bound when going forward
+    //and the start bound when going backward
+    stop: Bound<Vec<u8>>,
+}
+
+impl<T: Tree> BTreeIter<'_, T> {
+    //Start at the root and follow the link picked in every node down to a leaf
+    fn seek(&mut self, pick: impl Fn(&BNode) -> Result<u16>) -> Result<()> {
+        let root = self.tree.pages.get(self.tree.root)?;
+        let idx = pick(&root)?;
+        self.path.push((root, idx));
+        self.descend(pick)
+    }
+
+    //Extend the path from its last node down to a leaf, following the link picked in
+    //every child
+    fn descend(&mut self, pick: impl Fn(&BNode) -> Result<u16>) -> Result<()> {
+        loop {
+            let (node, idx) = self.path.last().unwrap();
+            if node.b_type()? == BNodeType::LeafNode {
+                return Ok(());
+            }
+            let child = self.tree.pages.get(node.get_ptr(*idx)?)?;
+            let idx = pick(&child)?;
+            self.path.push((child, idx));
+        }
+    }
+
+    //Move to the next kv pair, climbing up until a node has a next link and then
+    //descending to the first leaf under it
+    fn advance(&mut self) -> Result<()> {
+        let mut level = self.path.len();
+        loop {
+            if level == 0 {
+                self.path.clear();
+                return Ok(());
+            }
+            level -= 1;
+            let (node, idx) = &mut self.path[level];
+            if *idx + 1 < node.n_keys() {
+                *idx += 1;
+                break;
+            }
+        }
+        self.path.truncate(level + 1);
+        self.descend(|_| Ok(0))
+    }
+
+    //Move to the previous kv pair, climbing up until a node has a previous link and then
+    //descending to the last leaf under it
+    fn retreat(&mut self) -> Result<()> {
+        let mut level = self.path.len();
+        loop {
+            if level == 0 {
+                self.path.clear();
+                return Ok(());
+            }
+            level -= 1;
+            let (_, idx) = &mut self.path[level];
+            if *idx > 0 {
+                *idx -= 1;
+                break;
+            }
+        }
+        self.path.truncate(level + 1);
+        self.descend(|node| Ok(node.n_keys().saturating_sub(1)))
+    }
+
+    fn step(&mut self) -> Result<()> {
+        match self.direction {
+            Direction::Forward => self.advance(),
+            Direction::Backward => self.retreat(),
+        }
+    }
+
+    //Current kv pair if it is still inside the range, then move past it
+    fn next_pair(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            let Some((leaf, idx)) = self.path.last() else {
+                return Ok(None);
+            };
+            let key = leaf.get_key(*idx)?;
+            //The sentinel is not a user key
+            if key.is_empty() {
+                self.step()?;
+                continue;
+            }
+
+            let in_range = match (&self.stop, self.direction) {
+                (Bound::Included(end), Direction::Forward) => compare_keys(key, end).is_le(),
+                (Bound::Excluded(end), Direction::Forward) => compare_keys(key, end).is_lt(),
+                (Bound::Included(start), Direction::Backward) => compare_keys(key, start).is_ge(),
+                (Bound::Excluded(start), Direction::Backward) => compare_keys(key, start).is_gt(),
+                (Bound::Unbounded, _) => true,
+            };
+            if !in_range {
+                self.path.clear();
+                return Ok(None);
+            }
+
+            let pair = (key.to_vec(), self.tree.read_value(leaf, *idx)?);
+            self.step()?;
+            return Ok(Some(pair));
+        }
+    }
+}
+
+impl<T: Tree> Iterator for BTreeIter<'_, T> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    //An error ends the scan, it is returned once and the iterator is exhausted afterwards
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_pair() {
+            Ok(pair) => pair.map(Ok),
+            Err(err) => {
+                self.path.clear();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+//Sibling an underfull node gets merged into, with the sibling's pointer
+enum MergeDirection {
+    Left(u64),
+    Right(u64),
+    None,
+}
+
+//Copy entries from..to of node into the builder
+fn append_range(builder: &mut NodeBuilder, node: &BNode, from: u16, to: u16) -> Result<()> {
+    for idx in from..to {
+        builder.push(node.get_ptr(idx)?, node.get_key(idx)?, node.get_value(idx)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(keys: &[&[u8]]) -> BNode {
+        let mut builder = NodeBuilder::new(BNodeType::LeafNode, DEFAULT_PAGE_SIZE);
+        for key in keys {
+            builder.push(0, key, b"val");
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn lookup_le_in_empty_node() {
+        assert_eq!(leaf(&[]).lookup_le(b"key").unwrap(), 0);
+    }
+
+    #[test]
+    fn lookup_le_exact_match() {
+        let node = leaf(&[b"", b"b", b"d", b"f", b"h"]);
+        for (idx, key) in [b"b", b"d", b"f", b"h"].iter().enumerate() {
+            assert_eq!(node.lookup_le(*key).unwrap(), idx as u16 + 1);
+        }
+        assert_eq!(node.lookup_le(b"").unwrap(), 0);
+    }
+
+    #[test]
+    fn lookup_le_between_and_past_keys() {
+        let node = leaf(&[b"", b"b", b"d", b"f", b"h"]);
+        assert_eq!(node.lookup_le(b"a").unwrap(), 0);
+        assert_eq!(node.lookup_le(b"c").unwrap(), 1);
+        assert_eq!(node.lookup_le(b"g").unwrap(), 3);
+        assert_eq!(node.lookup_le(b"z").unwrap(), 4);
+        assert_eq!(node.lookup_le(b"hh").unwrap(), 4);
+    }
 }
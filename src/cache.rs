@@ -0,0 +1,186 @@
+use crate::b_node::BNode;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+//Bounded cache of decoded pages with least-recently-used eviction.
+//Every lookup stamps the page with the next tick, the oldest stamp is evicted first.
+//The cache of a pager is shared with the snapshots of SharedKV, which read trees of older
+//versions, so every page is cached with the oldest tree version it was read for. A flushed
+//page doesn't change while a tree reaching it is read and a reused page is removed before it
+//gets new contents, so the cached page is the page of every version from that one on. A
+//reader of an older version counts it as a miss: its tree could have had other contents at
+//that pointer if the page was reused before the reader started, held pages rule that out
+//today but the cache doesn't depend on it
+pub struct PageCache {
+    //Maximum number of pages kept, 0 disables the cache
+    capacity: usize,
+    //Cached page, the tick it was last used at and the version it was read for
+    pages: HashMap<u64, (BNode, u64, u64)>,
+    //Pointer of every cached page by the tick it was last used at
+    by_use: BTreeMap<u64, u64>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl PageCache {
+    pub fn new(capacity: usize) -> PageCache {
+        PageCache {
+            capacity,
+            pages: HashMap::new(),
+            by_use: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    //Change the number of pages kept, evicting the least recently used ones if needed
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    //Look up a page for a reader of the tree of version, counting the hit or miss
+    pub fn get(&mut self, ptr: u64, version: u64) -> Option<BNode> {
+        let Some((node, used, _)) = self
+            .pages
+            .get_mut(&ptr)
+            .filter(|(_, _, cached)| *cached <= version)
+        else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+
+        self.tick += 1;
+        self.by_use.remove(used);
+        self.by_use.insert(self.tick, ptr);
+        *used = self.tick;
+        Some(node.clone())
+    }
+
+    //Cache a page read for the tree of version
+    pub fn insert(&mut self, ptr: u64, node: BNode, version: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        let mut entry = (node, self.tick, version);
+        if let Some((_, used, cached)) = self.pages.get(&ptr) {
+            self.by_use.remove(used);
+            entry.2 = entry.2.min(*cached);
+        }
+        self.pages.insert(ptr, entry);
+        self.by_use.insert(self.tick, ptr);
+        self.evict();
+    }
+
+    //Forget a page, its pointer is about to hold different contents
+    pub fn remove(&mut self, ptr: u64) {
+        if let Some((_, used, _)) = self.pages.remove(&ptr) {
+            self.by_use.remove(&used);
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.pages.len() > self.capacity {
+            let (_, ptr) = self.by_use.pop_first().unwrap();
+            self.pages.remove(&ptr);
+        }
+    }
+}
+
+//Lock a cache shared between threads. Nothing in it can panic half way, so a poisoned lock
+//still guards a consistent cache
+pub(crate) fn lock(cache: &Arc<Mutex<PageCache>>) -> MutexGuard<'_, PageCache> {
+    cache.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_node::DEFAULT_PAGE_SIZE;
+
+    //Empty leaf, the tests tell pages apart by their pointers
+    fn node() -> BNode {
+        let mut page = vec![0; DEFAULT_PAGE_SIZE];
+        page[0] = 2;
+        BNode::parse(&page).unwrap()
+    }
+
+    //Pointers which are cached, looking them up uses them in the given order
+    fn cached(cache: &mut PageCache, pointers: &[u64]) -> Vec<u64> {
+        pointers
+            .iter()
+            .copied()
+            .filter(|ptr| cache.get(*ptr, 0).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn least_recently_used_page_is_evicted() {
+        let mut cache = PageCache::new(3);
+        for ptr in 1..=3 {
+            cache.insert(ptr, node(), 0);
+        }
+        //Using 1 makes 2 the least recently used page
+        assert!(cache.get(1, 0).is_some());
+        cache.insert(4, node(), 0);
+        assert_eq!(cache.pages.len(), 3);
+        assert_eq!(cached(&mut cache, &[1, 2, 3, 4]), [1, 3, 4]);
+
+        //Inserting a cached page again uses it too
+        cache.insert(1, node(), 0);
+        cache.insert(5, node(), 0);
+        assert_eq!(cached(&mut cache, &[1, 3, 4, 5]), [1, 4, 5]);
+    }
+
+    #[test]
+    fn shrinking_evicts_and_zero_disables() {
+        let mut cache = PageCache::new(4);
+        for ptr in 1..=4 {
+            cache.insert(ptr, node(), 0);
+        }
+        cache.set_capacity(2);
+        assert_eq!(cached(&mut cache, &[1, 2, 3, 4]), [3, 4]);
+
+        cache.set_capacity(0);
+        assert!(cache.pages.is_empty());
+        cache.insert(5, node(), 0);
+        assert!(cache.pages.is_empty());
+    }
+
+    #[test]
+    fn stats_count_hits_and_misses() {
+        let mut cache = PageCache::new(2);
+        cache.insert(1, node(), 0);
+        assert!(cache.get(1, 0).is_some());
+        assert!(cache.get(2, 0).is_none());
+        cache.remove(1);
+        assert!(cache.get(1, 0).is_none());
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+    }
+
+    #[test]
+    fn page_is_only_served_from_the_version_it_was_read_for() {
+        let mut cache = PageCache::new(2);
+        cache.insert(1, node(), 5);
+        assert!(cache.get(1, 4).is_none());
+        assert!(cache.get(1, 5).is_some());
+        assert!(cache.get(1, 9).is_some());
+        //Reading the page for an older version makes it valid from that one on
+        cache.insert(1, node(), 3);
+        assert!(cache.get(1, 3).is_some());
+        cache.insert(1, node(), 7);
+        assert!(cache.get(1, 3).is_some());
+    }
+}
@@ -0,0 +1,305 @@
+use crate::b_node::{BNodeType, BTree, Tree};
+use crate::compare::compare_keys;
+use crate::error::DbError;
+use crate::page_view::PageView;
+use crate::pager::Pager;
+use crate::rng::Rng;
+use crate::wal;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+//Consistency check of a whole tree, the equivalent of fsck.
+//Reading a page already validates it on its own (checksum, offsets, sizes within the page,
+//keys sorted within the node), the check adds what only shows across pages: every node's
+//keys lie between the keys linking to it and its right sibling, the link key is the first
+//key of the node, all leaves are at the same depth, overflow chains hold exactly the length
+//recorded in the leaf, and no page is reachable twice.
+//A broken page is reported and its subtree skipped, the rest of the tree is still checked.
+//A sampled check only follows a few random paths from the root down to a leaf, which bounds
+//its cost for large trees at the price of only catching what lies on these paths
+
+//Summary of a tree which passed the check
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    //Number of levels of nodes, 0 for the empty tree
+    pub depth: usize,
+    pub internal_nodes: u64,
+    pub leaf_nodes: u64,
+    pub overflow_pages: u64,
+    //Number of keys, the sentinel not included
+    pub keys: u64,
+    //Bytes used by all nodes from the start of the page to the end of the last kv pair
+    pub used_bytes: u64,
+}
+
+//Broken invariant found by the check
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub page: u64,
+    pub reason: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page {}: {}", self.page, self.reason)
+    }
+}
+
+//Outcome of checking a database file, see check_file
+#[derive(Clone, Debug)]
+pub struct FileCheck {
+    pub page_size: usize,
+    //Whether a write-ahead log left by a crash holds updates which are not in the file yet,
+    //the next regular open replays them and may fix what the check found
+    pub unreplayed_log: bool,
+    pub result: Result<TreeStats, Vec<Violation>>,
+}
+
+//Check the whole tree of the database file at path without changing the file, see
+//Pager::open_read_only. Opening fails on a file which is not a database file or whose
+//meta page is damaged
+pub fn check_file(path: impl AsRef<Path>) -> crate::error::Result<FileCheck> {
+    let tree = BTree::<Pager>::open_read_only(&path)?;
+    let log = std::fs::metadata(wal::log_path(path.as_ref()));
+    Ok(FileCheck {
+        page_size: tree.pages().page_size(),
+        unreplayed_log: log.is_ok_and(|log| log.len() > 0),
+        result: tree.check(),
+    })
+}
+
+impl<T: Tree> BTree<T> {
+    //Walk every page reachable from the root and verify the tree is consistent, returning all
+    //violations found instead of stopping at the first one
+    pub fn check(&self) -> Result<TreeStats, Vec<Violation>> {
+        let mut checker = Checker {
+            tree: self,
+            seen: HashSet::new(),
+            stats: TreeStats::default(),
+            violations: Vec::new(),
+            sample: None,
+        };
+        if self.root() != 0 {
+            checker.visit_node(self.root(), 1, None, None);
+        }
+
+        if checker.violations.is_empty() {
+            Ok(checker.stats)
+        } else {
+            Err(checker.violations)
+        }
+    }
+
+    //Check the nodes on descents random paths from the root to a leaf, the same seed takes
+    //the same paths. Returns the pages reached along with the violations found
+    pub(crate) fn check_sampled(
+        &self,
+        descents: usize,
+        seed: u64,
+    ) -> (HashSet<u64>, Vec<Violation>) {
+        let mut checker = Checker {
+            tree: self,
+            seen: HashSet::new(),
+            stats: TreeStats::default(),
+            violations: Vec::new(),
+            sample: Some(Rng(seed)),
+        };
+        let mut reached = HashSet::new();
+        let mut violations = Vec::new();
+        if self.root() != 0 {
+            //Paths share their upper nodes, so every descent starts with nothing seen and a
+            //problem in a shared node is only reported once
+            for _ in 0..descents {
+                checker.visit_node(self.root(), 1, None, None);
+                reached.extend(checker.seen.drain());
+                for violation in checker.violations.drain(..) {
+                    if !violations.contains(&violation) {
+                        violations.push(violation);
+                    }
+                }
+            }
+        }
+        (reached, violations)
+    }
+}
+
+struct Checker<'a, T: Tree> {
+    tree: &'a BTree<T>,
+    //Pages reached so far
+    seen: HashSet<u64>,
+    stats: TreeStats,
+    violations: Vec<Violation>,
+    //Picks the single link followed from every internal node in a sampled check
+    sample: Option<Rng>,
+}
+
+impl<T: Tree> Checker<'_, T> {
+    fn violation(&mut self, page: u64, reason: impl Into<String>) {
+        self.violations.push(Violation {
+            page,
+            reason: reason.into(),
+        });
+    }
+
+    //Mark a page as reached and load it, None when it was reached before or can't be read
+    fn load(&mut self, ptr: u64) -> Option<Vec<u8>> {
+        if !self.seen.insert(ptr) {
+            self.violation(ptr, "page is referenced more than once");
+            return None;
+        }
+        match self.tree.pages().get(ptr) {
+            Ok(node) => Some(node.data().to_vec()),
+            Err(DbError::Corruption { reason, .. }) => {
+                self.violation(ptr, reason);
+                None
+            }
+            Err(err) => {
+                self.violation(ptr, err.to_string());
+                None
+            }
+        }
+    }
+
+    //Check the subtree at ptr. link is the key linking to it from its parent and bound the
+    //key linking to its right sibling, its keys have to be in link..bound.
+    //The root has neither, its first key is the sentinel instead
+    fn visit_node(&mut self, ptr: u64, depth: usize, link: Option<&[u8]>, bound: Option<&[u8]>) {
+        let Some(page) = self.load(ptr) else {
+            return;
+        };
+        let node = match PageView::decode(&page) {
+            PageView::Node(node) => node,
+            other => {
+                self.violation(ptr, format!("expected a node, found {}", describe(&other)));
+                return;
+            }
+        };
+        self.stats.used_bytes += node.used_bytes as u64;
+
+        let (Some(first), Some(last)) = (node.entries.first(), node.entries.last()) else {
+            self.violation(ptr, "node has no keys");
+            return;
+        };
+        match link {
+            Some(link) if first.key != link => self.violation(
+                ptr,
+                format!(
+                    "first key {} differs from the key {} linking to the node",
+                    first.key.escape_ascii(),
+                    link.escape_ascii()
+                ),
+            ),
+            None if !first.key.is_empty() => {
+                self.violation(ptr, "first key of the tree is not the empty sentinel")
+            }
+            _ => {}
+        }
+        if let Some(bound) = bound
+            && compare_keys(last.key, bound).is_ge()
+        {
+            self.violation(
+                ptr,
+                format!(
+                    "last key {} is not below the key {} linking to the next node",
+                    last.key.escape_ascii(),
+                    bound.escape_ascii()
+                ),
+            );
+        }
+
+        match node.b_type {
+            BNodeType::InternalNode => {
+                self.stats.internal_nodes += 1;
+                if link.is_none() && node.entries.len() == 1 {
+                    self.violation(
+                        ptr,
+                        "root has a single link instead of being replaced by it",
+                    );
+                }
+                let links = match &mut self.sample {
+                    Some(rng) => {
+                        let idx = rng.below(node.entries.len() as u64) as usize;
+                        idx..idx + 1
+                    }
+                    None => 0..node.entries.len(),
+                };
+                for idx in links {
+                    let entry = &node.entries[idx];
+                    let next = node.entries.get(idx + 1).map(|next| next.key).or(bound);
+                    self.visit_node(entry.ptr, depth + 1, Some(entry.key), next);
+                }
+            }
+            BNodeType::LeafNode => {
+                self.stats.leaf_nodes += 1;
+                if self.stats.depth == 0 {
+                    self.stats.depth = depth;
+                } else if self.stats.depth != depth {
+                    self.violation(
+                        ptr,
+                        format!(
+                            "leaf is at depth {} but the first leaf is at depth {}",
+                            depth, self.stats.depth
+                        ),
+                    );
+                }
+
+                for entry in &node.entries {
+                    if !entry.key.is_empty() {
+                        self.stats.keys += 1;
+                    }
+                    //Parsing the leaf made sure the value of an overflow entry is its length
+                    if entry.ptr != 0 {
+                        let length = u64::from_le_bytes(entry.val.try_into().unwrap());
+                        self.visit_overflow(entry.ptr, length);
+                    }
+                }
+            }
+        }
+    }
+
+    //Check the overflow chain starting at head holds a value of length bytes
+    fn visit_overflow(&mut self, head: u64, length: u64) {
+        let mut stored = 0;
+        let mut ptr = head;
+        while ptr != 0 {
+            //A cycle reaches a page twice, so it ends the walk
+            let Some(page) = self.load(ptr) else {
+                return;
+            };
+            match PageView::decode(&page) {
+                PageView::Overflow { next, payload } => {
+                    self.stats.overflow_pages += 1;
+                    stored += payload.len() as u64;
+                    ptr = next;
+                }
+                other => {
+                    let found = describe(&other);
+                    self.violation(ptr, format!("expected an overflow page, found {}", found));
+                    return;
+                }
+            }
+        }
+
+        if stored != length {
+            self.violation(
+                head,
+                format!(
+                    "overflow chain holds {} bytes of a {} byte value",
+                    stored, length
+                ),
+            );
+        }
+    }
+}
+
+//Short description of what kind of page was found where another one was expected
+fn describe(page: &PageView) -> String {
+    match page {
+        PageView::Node(node) => format!("a {:?}", node.b_type),
+        PageView::FreeList { .. } => "a free list page".to_string(),
+        PageView::Overflow { .. } => "an overflow page".to_string(),
+        PageView::Meta(_) => "the meta page".to_string(),
+        PageView::Invalid { reason } => format!("an invalid page ({})", reason),
+    }
+}
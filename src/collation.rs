@@ -0,0 +1,269 @@
+use std::cmp::Ordering;
+
+//Collation of text keys following the layout of the Unicode Collation Algorithm.
+//Byte-wise ordering of UTF-8 puts "Zebra" before "apple" and "éclair" after "zebra",
+//so text is compared by its sort key instead:
+//  | primary (base letters) | 0x00 | secondary (accents) | 0x00 | tertiary (case) |
+//Comparing sort keys as bytes gives the collated order, which is what lets them be used
+//as tree keys without a custom comparator in BNode.
+//Only Latin accents and a few common ligatures are decomposed, everything else is
+//ordered by its lower-cased code point.
+
+//How many levels of the sort key take part in comparison
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    //Only base letters, "resume" == "Résumé"
+    Primary,
+    //Base letters and accents, "resume" < "résumé" but "résumé" == "Résumé"
+    Secondary,
+    //Base letters, accents and case, every distinct spelling is distinct
+    #[default]
+    Tertiary,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Collator {
+    strength: Strength,
+}
+
+//Accent marks, the secondary weight of a letter is its accent + 1
+const NO_ACCENT: u8 = 0;
+const GRAVE: u8 = 1;
+const ACUTE: u8 = 2;
+const CIRCUMFLEX: u8 = 3;
+const TILDE: u8 = 4;
+const DIAERESIS: u8 = 5;
+const RING: u8 = 6;
+const CEDILLA: u8 = 7;
+const STROKE: u8 = 8;
+const CARON: u8 = 9;
+const MACRON: u8 = 10;
+const BREVE: u8 = 11;
+const OGONEK: u8 = 12;
+const DOT: u8 = 13;
+const DOUBLE_ACUTE: u8 = 14;
+
+//Lower case accented letters and the base letters they sort with
+const DECOMPOSITIONS: &[(char, &str, u8)] = &[
+    ('à', "a", GRAVE),
+    ('á', "a", ACUTE),
+    ('â', "a", CIRCUMFLEX),
+    ('ã', "a", TILDE),
+    ('ä', "a", DIAERESIS),
+    ('å', "a", RING),
+    ('ā', "a", MACRON),
+    ('ă', "a", BREVE),
+    ('ą', "a", OGONEK),
+    ('æ', "ae", NO_ACCENT),
+    ('ç', "c", CEDILLA),
+    ('ć', "c", ACUTE),
+    ('č', "c", CARON),
+    ('ď', "d", CARON),
+    ('đ', "d", STROKE),
+    ('è', "e", GRAVE),
+    ('é', "e", ACUTE),
+    ('ê', "e", CIRCUMFLEX),
+    ('ë', "e", DIAERESIS),
+    ('ē', "e", MACRON),
+    ('ė', "e", DOT),
+    ('ę', "e", OGONEK),
+    ('ě', "e", CARON),
+    ('ğ', "g", BREVE),
+    ('ì', "i", GRAVE),
+    ('í', "i", ACUTE),
+    ('î', "i", CIRCUMFLEX),
+    ('ï', "i", DIAERESIS),
+    ('ī', "i", MACRON),
+    ('į', "i", OGONEK),
+    ('ł', "l", STROKE),
+    ('ñ', "n", TILDE),
+    ('ń', "n", ACUTE),
+    ('ň', "n", CARON),
+    ('ò', "o", GRAVE),
+    ('ó', "o", ACUTE),
+    ('ô', "o", CIRCUMFLEX),
+    ('õ', "o", TILDE),
+    ('ö', "o", DIAERESIS),
+    ('ø', "o", STROKE),
+    ('ō', "o", MACRON),
+    ('ő', "o", DOUBLE_ACUTE),
+    ('œ', "oe", NO_ACCENT),
+    ('ř', "r", CARON),
+    ('ś', "s", ACUTE),
+    ('ş', "s", CEDILLA),
+    ('š', "s", CARON),
+    ('ß', "ss", NO_ACCENT),
+    ('ţ', "t", CEDILLA),
+    ('ť', "t", CARON),
+    ('ù', "u", GRAVE),
+    ('ú', "u", ACUTE),
+    ('û', "u", CIRCUMFLEX),
+    ('ü', "u", DIAERESIS),
+    ('ū', "u", MACRON),
+    ('ů', "u", RING),
+    ('ű', "u", DOUBLE_ACUTE),
+    ('ý', "y", ACUTE),
+    ('ÿ', "y", DIAERESIS),
+    ('ź', "z", ACUTE),
+    ('ż', "z", DOT),
+    ('ž', "z", CARON),
+];
+
+//Separates the levels of a sort key, lower than any weight
+const LEVEL_SEPARATOR: u8 = 0;
+
+impl Collator {
+    pub fn new(strength: Strength) -> Collator {
+        Collator { strength }
+    }
+
+    pub fn strength(&self) -> Strength {
+        self.strength
+    }
+
+    //Compare two strings in collation order
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.sort_key(a).cmp(&self.sort_key(b))
+    }
+
+    //Build the byte string whose byte-wise order is the collation order of text
+    pub fn sort_key(&self, text: &str) -> Vec<u8> {
+        let mut primary = Vec::with_capacity(text.len() * 3);
+        let mut secondary = Vec::with_capacity(text.len());
+        let mut tertiary = Vec::with_capacity(text.len());
+
+        for c in text.chars() {
+            let case_weight = if c.is_uppercase() { 2 } else { 1 };
+
+            for lower in c.to_lowercase() {
+                let (base, accent) = decompose(lower);
+                for base_char in base {
+                    //Primary weights are 3 bytes with a first byte of at least 1,
+                    //so a shorter string always sorts before its extensions
+                    let code = base_char as u32;
+                    primary.push((code >> 16) as u8 + 1);
+                    primary.push((code >> 8) as u8);
+                    primary.push(code as u8);
+
+                    secondary.push(accent + 1);
+                    tertiary.push(case_weight);
+                }
+            }
+        }
+
+        let mut key = primary;
+        if self.strength >= Strength::Secondary {
+            key.push(LEVEL_SEPARATOR);
+            key.extend_from_slice(&secondary);
+        }
+        if self.strength >= Strength::Tertiary {
+            key.push(LEVEL_SEPARATOR);
+            key.extend_from_slice(&tertiary);
+        }
+        key
+    }
+}
+
+//Split a lower case character into the base letters it sorts with and its accent
+fn decompose(c: char) -> (Vec<char>, u8) {
+    match DECOMPOSITIONS
+        .iter()
+        .find(|(accented, _, _)| *accented == c)
+    {
+        Some((_, base, accent)) => (base.chars().collect(), *accent),
+        None => (vec![c], NO_ACCENT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRENGTHS: [Strength; 3] = [Strength::Primary, Strength::Secondary, Strength::Tertiary];
+
+    fn compare(strength: Strength, a: &str, b: &str) -> Ordering {
+        Collator::new(strength).compare(a, b)
+    }
+
+    #[test]
+    fn letters_sort_before_byte_order() {
+        let collator = Collator::default();
+        let mut words = ["zebra", "Zebra", "éclair", "apple", "eclair", "Apple"];
+        words.sort_by(|a, b| collator.compare(a, b));
+        assert_eq!(
+            words,
+            ["apple", "Apple", "eclair", "éclair", "zebra", "Zebra"]
+        );
+    }
+
+    #[test]
+    fn strength_decides_which_differences_count() {
+        assert_eq!(
+            compare(Strength::Primary, "resume", "Résumé"),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare(Strength::Secondary, "resume", "résumé"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(Strength::Secondary, "résumé", "Résumé"),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare(Strength::Tertiary, "résumé", "Résumé"),
+            Ordering::Less
+        );
+        //A difference in base letters outweighs accents and case anywhere in the string
+        for strength in STRENGTHS {
+            assert_eq!(compare(strength, "Éa", "eb"), Ordering::Less);
+            assert_eq!(compare(strength, "ab", "abc"), Ordering::Less);
+            assert_eq!(compare(strength, "", "a"), Ordering::Less);
+        }
+    }
+
+    #[test]
+    fn ligatures_sort_as_their_letters() {
+        assert_eq!(
+            compare(Strength::Primary, "straße", "strasse"),
+            Ordering::Equal
+        );
+        assert_eq!(compare(Strength::Secondary, "Æon", "aeon"), Ordering::Equal);
+        assert_eq!(
+            compare(Strength::Tertiary, "œuvre", "oeuvre"),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare(Strength::Primary, "strasse", "strat"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn sort_key_layout() {
+        //Three bytes of code point per base letter, then an accent and a case byte per letter
+        let key = Collator::new(Strength::Tertiary).sort_key("aÉ");
+        assert_eq!(key, [1, 0, b'a', 1, 0, b'e', 0, 1, ACUTE + 1, 0, 1, 2]);
+        assert_eq!(Collator::new(Strength::Secondary).sort_key("aÉ"), key[..9]);
+        assert_eq!(Collator::new(Strength::Primary).sort_key("aÉ"), key[..6]);
+        //Letters beyond the basic multilingual plane keep their full code point
+        assert_eq!(
+            Collator::new(Strength::Primary).sort_key("😀"),
+            [2, 0xf6, 0x00]
+        );
+        assert_eq!(Collator::new(Strength::Tertiary).sort_key(""), [0, 0]);
+    }
+
+    #[test]
+    fn letters_without_decomposition_sort_by_lower_case() {
+        assert_eq!(
+            compare(Strength::Secondary, "Ωmega", "ωmega"),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare(Strength::Tertiary, "ωmega", "Ωmega"),
+            Ordering::Less
+        );
+        assert_eq!(compare(Strength::Primary, "zeta", "ωmega"), Ordering::Less);
+    }
+}
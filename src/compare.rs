@@ -0,0 +1,76 @@
+use std::cmp::Ordering;
+
+//Byte-wise key comparison, which dominates the time spent searching nodes.
+//Instead of comparing one byte at a time, the common prefix of two keys is skipped in blocks:
+//16 bytes at a time with SSE2 where the CPU reports it, 8 bytes at a time otherwise.
+//Only the first differing byte (or the key lengths) decide the order, exactly like <[u8]>::cmp.
+
+//Compare two keys in byte-wise lexicographic order
+pub fn compare_keys(a: &[u8], b: &[u8]) -> Ordering {
+    let common = mismatch(a, b);
+    match (a.get(common), b.get(common)) {
+        (Some(x), Some(y)) => x.cmp(y),
+        _ => a.len().cmp(&b.len()),
+    }
+}
+
+//Return the length of the common prefix of a and b
+fn mismatch(a: &[u8], b: &[u8]) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse2") {
+            //Safety: the CPU supports SSE2
+            return unsafe { mismatch_sse2(a, b) };
+        }
+    }
+    mismatch_words(a, b)
+}
+
+//Compare 8 byte words, the first differing byte is found from the xor of the two words
+fn mismatch_words(a: &[u8], b: &[u8]) -> usize {
+    let len = a.len().min(b.len());
+    let mut idx = 0;
+
+    while idx + 8 <= len {
+        let x = u64::from_le_bytes(a[idx..idx + 8].try_into().unwrap());
+        let y = u64::from_le_bytes(b[idx..idx + 8].try_into().unwrap());
+        let diff = x ^ y;
+        if diff != 0 {
+            //Bytes were loaded little-endian, so the first differing byte is the lowest set one
+            return idx + diff.trailing_zeros() as usize / 8;
+        }
+        idx += 8;
+    }
+
+    while idx < len && a[idx] == b[idx] {
+        idx += 1;
+    }
+    idx
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn mismatch_sse2(a: &[u8], b: &[u8]) -> usize {
+    use std::arch::x86_64::{__m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8};
+
+    let len = a.len().min(b.len());
+    let mut idx = 0;
+
+    while idx + 16 <= len {
+        //Safety: idx + 16 <= len, so both loads stay inside their slices
+        let (x, y) = unsafe {
+            (
+                _mm_loadu_si128(a.as_ptr().add(idx) as *const __m128i),
+                _mm_loadu_si128(b.as_ptr().add(idx) as *const __m128i),
+            )
+        };
+        //One bit per byte, set where the bytes are equal
+        let equal = _mm_movemask_epi8(_mm_cmpeq_epi8(x, y)) as u32;
+        if equal != 0xFFFF {
+            return idx + (!equal).trailing_zeros() as usize;
+        }
+        idx += 16;
+    }
+
+    idx + mismatch_words(&a[idx..len], &b[idx..len])
+}
@@ -0,0 +1,116 @@
+use crate::check::Violation;
+use std::fmt;
+
+//Errors returned by the storage engine
+#[derive(Debug)]
+pub enum DbError {
+    //A page doesn't have a valid layout, e.g. it was damaged on disk.
+    //page is the page number when it is known at the point the problem was found
+    Corruption { page: Option<u64>, reason: String },
+    //Reading or writing the database file failed
+    Io(std::io::Error),
+    //The empty key is reserved for the sentinel at the start of the tree
+    EmptyKey,
+    //Key is longer than the largest key that fits in a node, after key encoding
+    KeyTooLarge { length: usize, max: usize },
+    //Page size given for a new database file is not a power of two between 4KB and 64KB
+    InvalidPageSize(usize),
+    //An earlier update failed half way, the store has to be reopened
+    Poisoned,
+    //The database file is open elsewhere in a way that conflicts with this open, see Pager::open
+    Locked,
+    //Table with this name doesn't exist
+    UnknownTable(String),
+    //Table with this name was created before
+    TableExists(String),
+    //Table definition is not usable, e.g. it names a column twice
+    InvalidSchema(String),
+    //Row or primary key doesn't match the columns of its table
+    InvalidRow(String),
+    //Insert of a row whose primary key is already taken
+    DuplicateKey,
+    //SQL statement can't be parsed or run, e.g. it names a column the table doesn't have
+    InvalidQuery(String),
+    //Integrity check run when opening the file found it damaged, see selftest
+    SelfTestFailed(Vec<Violation>),
+}
+
+pub type Result<T> = std::result::Result<T, DbError>;
+
+impl DbError {
+    pub fn corruption(reason: impl Into<String>) -> DbError {
+        DbError::Corruption {
+            page: None,
+            reason: reason.into(),
+        }
+    }
+
+    //Attach the number of the page the error was found in
+    pub fn with_page(self, page: u64) -> DbError {
+        match self {
+            DbError::Corruption { reason, .. } => DbError::Corruption {
+                page: Some(page),
+                reason,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Corruption {
+                page: Some(page),
+                reason,
+            } => write!(f, "corrupted page {}: {}", page, reason),
+            DbError::Corruption { page: None, reason } => write!(f, "corrupted page: {}", reason),
+            DbError::Io(err) => write!(f, "I/O error: {}", err),
+            DbError::EmptyKey => write!(f, "keys can't be empty"),
+            DbError::KeyTooLarge { length, max } => {
+                write!(f, "key of {} bytes is longer than {} bytes", length, max)
+            }
+            DbError::InvalidPageSize(size) => write!(
+                f,
+                "page size {} is not a power of two between 4KB and 64KB",
+                size
+            ),
+            DbError::Poisoned => write!(
+                f,
+                "an earlier update failed, the database has to be reopened"
+            ),
+            DbError::Locked => write!(f, "the database file is in use by another open"),
+            DbError::UnknownTable(name) => write!(f, "table {} doesn't exist", name),
+            DbError::TableExists(name) => write!(f, "table {} already exists", name),
+            DbError::InvalidSchema(reason) => write!(f, "invalid table definition: {}", reason),
+            DbError::InvalidRow(reason) => write!(f, "invalid row: {}", reason),
+            DbError::DuplicateKey => write!(f, "a row with this primary key already exists"),
+            DbError::InvalidQuery(reason) => write!(f, "invalid query: {}", reason),
+            DbError::SelfTestFailed(violations) => {
+                write!(f, "self-test failed")?;
+                if let Some(first) = violations.first() {
+                    write!(f, " at {}", first)?;
+                }
+                if violations.len() > 1 {
+                    write!(f, " and {} more places", violations.len() - 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(err: std::io::Error) -> DbError {
+        DbError::Io(err)
+    }
+}
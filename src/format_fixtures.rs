@@ -0,0 +1,141 @@
+use crate::b_node::{BTree, DEFAULT_PAGE_SIZE};
+use crate::error::{DbError, Result};
+use crate::key_encoding::KeyEncoding;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+
+//Small canonical database files for catching unintended changes of the file format.
+//A sample is written by a fixed sequence of updates and the same sequence always produces
+//the same bytes, so a freshly written sample can be compared byte for byte against a
+//golden copy written by an earlier build. Loading a golden copy checks that the current
+//code still reads files written by the older one.
+//When the format changes on purpose the golden files are rewritten; a sample whose
+//updates change gets a new version instead, so old golden files stay meaningful.
+//
+//Version 1 holds a few hundred small entries, one value stored in overflow pages and a
+//free list left behind by deleted keys, written in several flushes with 4KB pages
+
+//Updates are flushed in batches of this many, so the sample has more than one tree version
+const V1_BATCH: usize = 50;
+
+//Write sample version 1 to path, replacing the file if it exists
+pub fn write_v1_sample(path: impl AsRef<Path>) -> Result<()> {
+    //Opening an existing file would add to it instead of writing the sample from scratch
+    File::create(&path)?;
+    let mut tree = BTree::open(&path, KeyEncoding::Raw, DEFAULT_PAGE_SIZE)?;
+    //Grow the file a page at a time, so it ends with the last page in use instead of an
+    //extent of unused space
+    tree.pages_mut().set_extent_size(DEFAULT_PAGE_SIZE as u64);
+
+    for batch in v1_updates().chunks(V1_BATCH) {
+        for (key, val) in batch {
+            match val {
+                Some(val) => tree.insert(key, val)?,
+                None => {
+                    tree.delete(key)?;
+                }
+            }
+        }
+        tree.flush()?;
+    }
+    Ok(())
+}
+
+//Read every entry of the sample at path, checking the file can be opened and its tree walked
+pub fn load_v1_sample(path: impl AsRef<Path>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    //Opening creates missing files, a missing golden file must not turn into an empty sample
+    if !path.as_ref().exists() {
+        return Err(io::Error::from(io::ErrorKind::NotFound).into());
+    }
+    let tree = BTree::open(&path, KeyEncoding::Raw, DEFAULT_PAGE_SIZE)?;
+    tree.scan(Bound::Unbounded, Bound::Unbounded)?.collect()
+}
+
+//Entries sample version 1 holds, in key order
+pub fn v1_sample_entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut entries = BTreeMap::new();
+    for (key, val) in v1_updates() {
+        match val {
+            Some(val) => entries.insert(key, val),
+            None => entries.remove(&key),
+        };
+    }
+    entries.into_iter().collect()
+}
+
+//Load the sample at path and check it holds exactly the entries of version 1
+pub fn check_v1_sample(path: impl AsRef<Path>) -> Result<()> {
+    let loaded = load_v1_sample(path)?;
+    let expected = v1_sample_entries();
+    if loaded.len() != expected.len() {
+        return Err(DbError::corruption(format!(
+            "sample holds {} entries instead of {}",
+            loaded.len(),
+            expected.len()
+        )));
+    }
+    if let Some((entry, _)) = loaded.iter().zip(&expected).find(|(a, b)| a != b) {
+        return Err(DbError::corruption(format!(
+            "sample entry {} differs from version 1",
+            entry.0.escape_ascii()
+        )));
+    }
+    Ok(())
+}
+
+//Updates writing sample version 1, None deletes the key
+fn v1_updates() -> Vec<(Vec<u8>, Option<Vec<u8>>)> {
+    let mut updates = Vec::new();
+    for idx in 0..300u32 {
+        let key = format!("key{:04}", idx).into_bytes();
+        let val = format!("value {} ", idx)
+            .repeat(1 + idx as usize % 7)
+            .into_bytes();
+        updates.push((key, Some(val)));
+    }
+    //Large enough for a chain of several overflow pages
+    let large = (0..10_000u32).map(|idx| (idx % 251) as u8).collect();
+    updates.push((b"large".to_vec(), Some(large)));
+    for idx in (0..300u32).step_by(3) {
+        updates.push((format!("key{:04}", idx).into_bytes(), None));
+    }
+    for idx in (1..300u32).step_by(10) {
+        let val = format!("replaced {}", idx).into_bytes();
+        updates.push((format!("key{:04}", idx).into_bytes(), Some(val)));
+    }
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    //Golden copy of sample version 1. After an intended format change it is written again by
+    //running v1_sample_matches_golden alone with REWRITE_GOLDEN set
+    const V1_GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/v1.db");
+
+    #[test]
+    fn golden_v1_loads() {
+        check_v1_sample(V1_GOLDEN).unwrap();
+    }
+
+    #[test]
+    fn v1_sample_matches_golden() {
+        if std::env::var_os("REWRITE_GOLDEN").is_some() {
+            write_v1_sample(V1_GOLDEN).unwrap();
+        }
+        let path = TempPath::new("format-v1-sample");
+        write_v1_sample(&path).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        let golden = std::fs::read(V1_GOLDEN).unwrap();
+        assert!(
+            written == golden,
+            "sample version 1 differs from {}",
+            V1_GOLDEN
+        );
+    }
+}
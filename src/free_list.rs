@@ -0,0 +1,91 @@
+use crate::b_node::{CHECKSUM_SIZE, valid_page_size};
+use crate::error::{DbError, Result};
+
+//On-disk free list: a linked list of pages recording page numbers which are not used by
+//the tree and can be handed out again, so deleted pages don't make the file grow forever.
+//The list is stored in free pages themselves, so it never takes extra space.
+//
+//Free list page format:
+//| type | count | next | pointers  |
+//|  2B  |  2B   |  8B  | count * 8B |
+//next is the following page of the list, 0 for the last one
+
+//Page type of free list pages, following the node types 1 and 2
+pub(crate) const FREE_LIST_TYPE: u16 = 3;
+const FREE_LIST_HEADER: usize = 12;
+
+//Number of pointers a single free list page holds
+pub(crate) fn capacity(page_size: usize) -> usize {
+    (page_size - FREE_LIST_HEADER - CHECKSUM_SIZE) / 8
+}
+
+//Lay out one page of the list
+pub(crate) fn encode_page(page_size: usize, next: u64, pointers: &[u64]) -> Vec<u8> {
+    assert!(pointers.len() <= capacity(page_size));
+
+    let mut page = vec![0; page_size];
+    page[0..2].copy_from_slice(&FREE_LIST_TYPE.to_le_bytes());
+    page[2..4].copy_from_slice(&(pointers.len() as u16).to_le_bytes());
+    page[4..12].copy_from_slice(&next.to_le_bytes());
+    for (idx, ptr) in pointers.iter().enumerate() {
+        let position = FREE_LIST_HEADER + 8 * idx;
+        page[position..position + 8].copy_from_slice(&ptr.to_le_bytes());
+    }
+    page
+}
+
+//Decode one page of the list into the next page pointer and the free pages it records
+pub(crate) fn decode_page(page: &[u8]) -> Result<(u64, Vec<u64>)> {
+    if !valid_page_size(page.len()) {
+        return Err(DbError::corruption(format!(
+            "free list page is {} bytes, which is not a valid page size",
+            page.len()
+        )));
+    }
+
+    let page_type = u16::from_le_bytes(page[0..2].try_into().unwrap());
+    if page_type != FREE_LIST_TYPE {
+        return Err(DbError::corruption(format!(
+            "expected a free list page, found page type {}",
+            page_type
+        )));
+    }
+    let count = u16::from_le_bytes(page[2..4].try_into().unwrap()) as usize;
+    if count > capacity(page.len()) {
+        return Err(DbError::corruption(format!(
+            "free list page holds {} pointers, at most {} fit",
+            count,
+            capacity(page.len())
+        )));
+    }
+
+    let next = u64::from_le_bytes(page[4..12].try_into().unwrap());
+    let pointers = (0..count)
+        .map(|idx| {
+            let position = FREE_LIST_HEADER + 8 * idx;
+            u64::from_le_bytes(page[position..position + 8].try_into().unwrap())
+        })
+        .collect();
+    Ok((next, pointers))
+}
+
+//Split the free pages into the pages which will hold the list and the pages they record.
+//Returns the encoded list pages with their page numbers, the first one is the head
+pub(crate) fn build(page_size: usize, free: &[u64]) -> Vec<(u64, Vec<u8>)> {
+    //k list pages record the other n - k pages, so k * (capacity + 1) >= n is enough
+    let list_pages = free.len().div_ceil(capacity(page_size) + 1);
+    let (holders, recorded) = free.split_at(list_pages);
+
+    let mut chunks = recorded.chunks(capacity(page_size));
+    holders
+        .iter()
+        .enumerate()
+        .map(|(idx, &ptr)| {
+            let next = holders.get(idx + 1).copied().unwrap_or(0);
+            (
+                ptr,
+                encode_page(page_size, next, chunks.next().unwrap_or(&[])),
+            )
+        })
+        .collect()
+}
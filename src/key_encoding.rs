@@ -0,0 +1,83 @@
+#[cfg(feature = "collation")]
+use crate::collation::{Collator, Strength};
+use std::borrow::Cow;
+
+//Encoding applied to every user key before it is stored in or looked up from a tree.
+//It is chosen once per tree and saved in the tree metadata by its id, so keys that
+//look equal to the user (e.g. "Key" and "key" with case folding) always end up as the same entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyEncoding {
+    //Keys are stored exactly as given
+    #[default]
+    Raw,
+    //ASCII letters are folded to lower case, all other bytes are kept as they are
+    AsciiCaseFold,
+    //Keys are treated as UTF-8 and folded to lower case using Unicode rules.
+    //Keys which are not valid UTF-8 are stored unchanged
+    UnicodeCaseFold,
+    //Keys are treated as UTF-8 and replaced by their collation sort key, so the
+    //tree orders them in collation order instead of by raw bytes.
+    //Only the sort key is stored and it can't be turned back into text, so scans return sort
+    //keys. Applications needing the text as it was set keep it in the value.
+    //Keys which are not valid UTF-8 are stored unchanged
+    #[cfg(feature = "collation")]
+    Collated(Strength),
+}
+
+impl KeyEncoding {
+    //Return the id under which this encoding is persisted in tree metadata
+    pub fn id(self) -> u8 {
+        match self {
+            KeyEncoding::Raw => 0,
+            KeyEncoding::AsciiCaseFold => 1,
+            KeyEncoding::UnicodeCaseFold => 2,
+            #[cfg(feature = "collation")]
+            KeyEncoding::Collated(Strength::Primary) => 3,
+            #[cfg(feature = "collation")]
+            KeyEncoding::Collated(Strength::Secondary) => 4,
+            #[cfg(feature = "collation")]
+            KeyEncoding::Collated(Strength::Tertiary) => 5,
+        }
+    }
+
+    //Restore an encoding from its persisted id
+    pub fn from_id(id: u8) -> Option<KeyEncoding> {
+        match id {
+            0 => Some(KeyEncoding::Raw),
+            1 => Some(KeyEncoding::AsciiCaseFold),
+            2 => Some(KeyEncoding::UnicodeCaseFold),
+            #[cfg(feature = "collation")]
+            3 => Some(KeyEncoding::Collated(Strength::Primary)),
+            #[cfg(feature = "collation")]
+            4 => Some(KeyEncoding::Collated(Strength::Secondary)),
+            #[cfg(feature = "collation")]
+            5 => Some(KeyEncoding::Collated(Strength::Tertiary)),
+            _ => None,
+        }
+    }
+
+    //Encode the key, borrowing it when the encoding leaves it unchanged
+    pub fn encode(self, key: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            KeyEncoding::Raw => Cow::Borrowed(key),
+            KeyEncoding::AsciiCaseFold => {
+                if key.iter().any(|b| b.is_ascii_uppercase()) {
+                    Cow::Owned(key.to_ascii_lowercase())
+                } else {
+                    Cow::Borrowed(key)
+                }
+            }
+            KeyEncoding::UnicodeCaseFold => match std::str::from_utf8(key) {
+                Ok(text) if text.chars().any(|c| c.is_uppercase()) => {
+                    Cow::Owned(text.to_lowercase().into_bytes())
+                }
+                _ => Cow::Borrowed(key),
+            },
+            #[cfg(feature = "collation")]
+            KeyEncoding::Collated(strength) => match std::str::from_utf8(key) {
+                Ok(text) => Cow::Owned(Collator::new(strength).sort_key(text)),
+                Err(_) => Cow::Borrowed(key),
+            },
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Order-preserving key encodings.
+//The tree compares keys byte by byte, so numbers stored with to_le_bytes() sort by their
+//lowest byte first (256 < 1) and negative numbers sort after positive ones.
+//Every encoder here returns bytes whose byte-wise order matches the order of the values:
+//integers are big-endian with the sign bit flipped, floats additionally flip all other bits
+//when negative, and every decoder reverses its encoder exactly.
+
+//Unsigned integers only need big-endian byte order
+pub fn encode_u64(value: u64) -> [u8; 8] {
+    value.to_be_bytes()
+}
+
+pub fn decode_u64(bytes: [u8; 8]) -> u64 {
+    u64::from_be_bytes(bytes)
+}
+
+//Flipping the sign bit moves negative numbers below positive ones
+pub fn encode_i64(value: i64) -> [u8; 8] {
+    ((value as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+pub fn decode_i64(bytes: [u8; 8]) -> i64 {
+    (u64::from_be_bytes(bytes) ^ (1 << 63)) as i64
+}
+
+//Positive floats get their sign bit set so they sort above negative ones, negative floats
+//get all bits flipped so a larger magnitude sorts lower.
+//All NaNs are encoded as a single NaN which sorts after positive infinity, and -0.0
+//sorts just before 0.0
+pub fn encode_f64(value: f64) -> [u8; 8] {
+    let bits = if value.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        value.to_bits()
+    };
+
+    let encoded = if bits & (1 << 63) != 0 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    };
+    encoded.to_be_bytes()
+}
+
+pub fn decode_f64(bytes: [u8; 8]) -> f64 {
+    let encoded = u64::from_be_bytes(bytes);
+
+    let bits = if encoded & (1 << 63) != 0 {
+        encoded & !(1 << 63)
+    } else {
+        !encoded
+    };
+    f64::from_bits(bits)
+}
+
+//UUIDs are already stored big-endian, so their 16 bytes sort in the same order as their text form
+pub fn encode_uuid(uuid: [u8; 16]) -> [u8; 16] {
+    uuid
+}
+
+pub fn decode_uuid(bytes: [u8; 16]) -> [u8; 16] {
+    bytes
+}
+
+//Timestamps are stored as signed nanoseconds since the Unix epoch, which covers the years 1677-2262.
+//Times outside of that range are clamped to its ends, so they still sort before or after every
+//time inside of it but decode to the end they were clamped to
+pub fn encode_timestamp(time: SystemTime) -> [u8; 8] {
+    let nanos = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => i64::try_from(after.as_nanos()).unwrap_or(i64::MAX),
+        Err(before) => i64::try_from(before.duration().as_nanos())
+            .map(|nanos| -nanos)
+            .unwrap_or(i64::MIN),
+    };
+    encode_i64(nanos)
+}
+
+pub fn decode_timestamp(bytes: [u8; 8]) -> SystemTime {
+    let nanos = decode_i64(bytes);
+    if nanos >= 0 {
+        UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_nanos(nanos.unsigned_abs())
+    }
+}
+
+//Concatenate encoded parts into one key, ordered by the first part, then the second and so on.
+//This only preserves order when every part has a fixed width, like the encodings above.
+//Keys with variable-length parts should be built with Tuple instead
+pub fn compose(parts: &[&[u8]]) -> Vec<u8> {
+    parts.concat()
+}
+
+//A single element of a tuple key
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Str(String),
+}
+
+//Type tags written before every tuple element.
+//Elements of different types are ordered by their tag, so NULL sorts before everything else
+const TAG_NULL: u8 = 0x01;
+const TAG_INT: u8 = 0x02;
+const TAG_FLOAT: u8 = 0x03;
+const TAG_BYTES: u8 = 0x04;
+const TAG_STR: u8 = 0x05;
+//Larger than every tag, see Tuple::encode_prefix_end
+const PREFIX_END: u8 = 0xFF;
+
+//Variable-length elements end with TERMINATOR, a 0x00 inside the element is written as 0x00 ESCAPE.
+//Since ESCAPE is larger than anything that can follow a terminator, a prefix always sorts
+//before the longer element and the elements after it are never compared against its bytes
+const TERMINATOR: u8 = 0x00;
+const ESCAPE: u8 = 0xFF;
+
+//Codec for composite keys made of several values.
+//Tuples compare element by element: encode(a) < encode(b) exactly when a < b comparing
+//the first elements, then the second ones and so on, with a shorter tuple sorting first
+pub struct Tuple;
+
+impl Tuple {
+    pub fn encode(values: &[Value]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for value in values {
+            match value {
+                Value::Null => out.push(TAG_NULL),
+                Value::Int(v) => {
+                    out.push(TAG_INT);
+                    out.extend_from_slice(&encode_i64(*v));
+                }
+                Value::Float(v) => {
+                    out.push(TAG_FLOAT);
+                    out.extend_from_slice(&encode_f64(*v));
+                }
+                Value::Bytes(v) => {
+                    out.push(TAG_BYTES);
+                    encode_escaped(&mut out, v);
+                }
+                Value::Str(v) => {
+                    out.push(TAG_STR);
+                    encode_escaped(&mut out, v.as_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    //Encode a key which sorts after every tuple starting with values, values itself included.
+    //Every element starts with a tag below PREFIX_END, so no continuation of values reaches it
+    pub fn encode_prefix_end(values: &[Value]) -> Vec<u8> {
+        let mut out = Tuple::encode(values);
+        out.push(PREFIX_END);
+        out
+    }
+
+    //Decode a tuple, returns None if the bytes were not produced by encode
+    pub fn decode(mut bytes: &[u8]) -> Option<Vec<Value>> {
+        let mut values = Vec::new();
+        while let Some((&tag, rest)) = bytes.split_first() {
+            let (value, rest) = match tag {
+                TAG_NULL => (Value::Null, rest),
+                TAG_INT => {
+                    let (number, rest) = rest.split_first_chunk::<8>()?;
+                    (Value::Int(decode_i64(*number)), rest)
+                }
+                TAG_FLOAT => {
+                    let (number, rest) = rest.split_first_chunk::<8>()?;
+                    (Value::Float(decode_f64(*number)), rest)
+                }
+                TAG_BYTES => {
+                    let (data, rest) = decode_escaped(rest)?;
+                    (Value::Bytes(data), rest)
+                }
+                TAG_STR => {
+                    let (data, rest) = decode_escaped(rest)?;
+                    (Value::Str(String::from_utf8(data).ok()?), rest)
+                }
+                _ => return None,
+            };
+            values.push(value);
+            bytes = rest;
+        }
+        Some(values)
+    }
+}
+
+fn encode_escaped(out: &mut Vec<u8>, data: &[u8]) {
+    for &byte in data {
+        out.push(byte);
+        if byte == TERMINATOR {
+            out.push(ESCAPE);
+        }
+    }
+    out.push(TERMINATOR);
+}
+
+//Read an escaped element, returns it together with the bytes following its terminator
+fn decode_escaped(bytes: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut data = Vec::new();
+    let mut idx = 0;
+    loop {
+        match *bytes.get(idx)? {
+            TERMINATOR if bytes.get(idx + 1) == Some(&ESCAPE) => {
+                data.push(TERMINATOR);
+                idx += 2;
+            }
+            TERMINATOR => return Some((data, &bytes[idx + 1..])),
+            byte => {
+                data.push(byte);
+                idx += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Every encoding has to be strictly increasing over values given in ascending order
+    fn assert_ascending<T: std::fmt::Debug, const N: usize>(
+        values: &[T],
+        encode: impl Fn(&T) -> [u8; N],
+    ) {
+        for pair in values.windows(2) {
+            assert!(
+                encode(&pair[0]) < encode(&pair[1]),
+                "{:?} doesn't sort before {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn integers_keep_order_and_round_trip() {
+        let signed = [i64::MIN, i64::MIN + 1, -256, -1, 0, 1, 255, 256, i64::MAX];
+        assert_ascending(&signed, |value| encode_i64(*value));
+        for value in signed {
+            assert_eq!(decode_i64(encode_i64(value)), value);
+        }
+
+        let unsigned = [0, 1, 255, 256, 1 << 32, u64::MAX - 1, u64::MAX];
+        assert_ascending(&unsigned, |value| encode_u64(*value));
+        for value in unsigned {
+            assert_eq!(decode_u64(encode_u64(value)), value);
+        }
+    }
+
+    #[test]
+    fn uuids_sort_like_their_text() {
+        let uuids = [
+            [0; 16],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0],
+            [0x12; 16],
+            [0xff; 16],
+        ];
+        assert_ascending(&uuids, |uuid| encode_uuid(*uuid));
+        let text = |uuid: &[u8; 16]| {
+            uuid.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        for pair in uuids.windows(2) {
+            assert!(text(&pair[0]) < text(&pair[1]));
+        }
+        for uuid in uuids {
+            assert_eq!(decode_uuid(encode_uuid(uuid)), uuid);
+        }
+    }
+
+    #[test]
+    fn timestamps_keep_order_and_round_trip() {
+        let times = [
+            UNIX_EPOCH - Duration::from_secs(1 << 32),
+            UNIX_EPOCH - Duration::from_nanos(1),
+            UNIX_EPOCH,
+            UNIX_EPOCH + Duration::from_nanos(1),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            UNIX_EPOCH + Duration::from_secs(1 << 33),
+        ];
+        assert_ascending(&times, |time| encode_timestamp(*time));
+        for time in times {
+            assert_eq!(decode_timestamp(encode_timestamp(time)), time);
+        }
+    }
+
+    #[test]
+    fn timestamps_out_of_range_are_clamped() {
+        let latest = UNIX_EPOCH + Duration::from_nanos(i64::MAX as u64);
+        let earliest = UNIX_EPOCH - Duration::from_nanos(1 << 63);
+        let far_future = latest + Duration::from_secs(1000 * 365 * 86400);
+        let far_past = earliest - Duration::from_secs(1000 * 365 * 86400);
+
+        assert_eq!(encode_timestamp(far_future), encode_timestamp(latest));
+        assert_eq!(
+            encode_timestamp(latest + Duration::from_nanos(1)),
+            encode_i64(i64::MAX)
+        );
+        assert_eq!(encode_timestamp(far_past), encode_timestamp(earliest));
+        assert_eq!(
+            encode_timestamp(earliest - Duration::from_nanos(1)),
+            encode_i64(i64::MIN)
+        );
+        assert_eq!(decode_timestamp(encode_timestamp(far_future)), latest);
+        assert_eq!(decode_timestamp(encode_timestamp(far_past)), earliest);
+        assert_ascending(&[far_past, UNIX_EPOCH, far_future], |time| {
+            encode_timestamp(*time)
+        });
+    }
+
+    fn str(text: &str) -> Value {
+        Value::Str(text.to_string())
+    }
+
+    //Tuples in ascending order, comparing element by element
+    fn ascending_tuples() -> Vec<Vec<Value>> {
+        vec![
+            vec![],
+            vec![Value::Null],
+            vec![Value::Null, Value::Int(1)],
+            vec![Value::Int(-5)],
+            vec![Value::Int(2)],
+            vec![Value::Int(2), Value::Null],
+            vec![Value::Int(2), str("")],
+            vec![Value::Int(2), str("a")],
+            vec![Value::Int(2), str("a"), Value::Int(0)],
+            vec![Value::Int(2), str("a\0")],
+            vec![Value::Int(2), str("a\0\0")],
+            vec![Value::Int(2), str("a\u{1}")],
+            vec![Value::Int(2), str("ab")],
+            vec![Value::Int(2), str("a\u{ff}")],
+            vec![Value::Int(3)],
+            vec![Value::Float(f64::NEG_INFINITY)],
+            vec![Value::Float(-0.5)],
+            vec![Value::Float(0.0)],
+            vec![Value::Float(f64::NAN)],
+            vec![Value::Bytes(vec![])],
+            vec![Value::Bytes(vec![0]), Value::Int(7)],
+            vec![Value::Bytes(vec![0, 0])],
+            vec![Value::Bytes(vec![0, 0xff])],
+            vec![Value::Bytes(vec![0xff])],
+            vec![Value::Bytes(vec![0xff, 0])],
+            vec![str("")],
+        ]
+    }
+
+    #[test]
+    fn tuples_round_trip() {
+        for tuple in ascending_tuples() {
+            let decoded = Tuple::decode(&Tuple::encode(&tuple)).unwrap();
+            //NaN isn't equal to itself, compare it through its encoding
+            assert_eq!(Tuple::encode(&decoded), Tuple::encode(&tuple));
+            if !matches!(tuple[..], [Value::Float(value)] if value.is_nan()) {
+                assert_eq!(decoded, tuple);
+            }
+        }
+    }
+
+    #[test]
+    fn tuples_keep_order() {
+        let tuples = ascending_tuples();
+        for pair in tuples.windows(2) {
+            assert!(
+                Tuple::encode(&pair[0]) < Tuple::encode(&pair[1]),
+                "{:?} doesn't sort before {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn embedded_terminators_are_escaped() {
+        let tuple = [Value::Bytes(vec![0, 0xff, 0]), Value::Int(1)];
+        let encoded = Tuple::encode(&tuple);
+        assert_eq!(
+            encoded[..8],
+            [TAG_BYTES, 0, ESCAPE, 0xff, 0, ESCAPE, TERMINATOR, TAG_INT]
+        );
+        assert_eq!(Tuple::decode(&encoded).unwrap(), tuple);
+    }
+
+    #[test]
+    fn prefix_sorts_before_its_extensions() {
+        let prefix = [Value::Int(2), str("a")];
+        let begin = Tuple::encode(&prefix);
+        let end = Tuple::encode_prefix_end(&prefix);
+        assert!(begin < end);
+
+        for tuple in ascending_tuples() {
+            let encoded = Tuple::encode(&tuple);
+            let extends = tuple.len() >= prefix.len() && tuple[..prefix.len()] == prefix;
+            assert_eq!(
+                begin <= encoded && encoded < end,
+                extends,
+                "{:?} against the prefix",
+                tuple
+            );
+        }
+    }
+
+    #[test]
+    fn damaged_tuples_are_rejected() {
+        let encoded = Tuple::encode(&[Value::Int(1), str("text")]);
+        //Cut inside the integer and inside the string
+        assert!(Tuple::decode(&encoded[..5]).is_none());
+        assert!(Tuple::decode(&encoded[..encoded.len() - 1]).is_none());
+        assert!(Tuple::decode(&[0x42]).is_none());
+        assert!(Tuple::decode(&[TAG_STR, 0xff, 0xfe, TERMINATOR]).is_none());
+    }
+}
@@ -0,0 +1,553 @@
+use crate::b_node::{BTree, BTreeIter, DEFAULT_PAGE_SIZE};
+use crate::cache::CacheStats;
+use crate::error::{DbError, Result};
+use crate::key_encoding::KeyEncoding;
+#[cfg(feature = "latency-histograms")]
+use crate::latency::{Latency, LatencyStats, Operation};
+use crate::pager::Pager;
+use crate::selftest::{self, SELF_TEST_DESCENTS};
+#[cfg(feature = "latency-histograms")]
+use std::cell::RefCell;
+use std::ops::Bound;
+use std::path::Path;
+#[cfg(feature = "latency-histograms")]
+use std::time::Instant;
+
+//Key-value store kept in a single database file.
+//Every update is flushed before it returns, so a set or del that succeeded survives a crash.
+//An update which fails half way, e.g. on an I/O error or a panic caught further up, can leave
+//the pager with pages of the unfinished update, so the store is poisoned and every later call
+//fails with DbError::Poisoned. The file still holds the last successful update, reopening it
+//recovers
+pub struct KV {
+    tree: BTree<Pager>,
+    poisoned: bool,
+    //Latencies of the operations so far. Reads only borrow the store, so it is in a RefCell
+    #[cfg(feature = "latency-histograms")]
+    latency: RefCell<Latency>,
+}
+
+impl KV {
+    //Open the store at path, creating the file with raw keys and 4KB pages if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> Result<KV> {
+        KV::open_with_encoding(path, KeyEncoding::Raw)
+    }
+
+    //Open the store like open, creating the file with keys encoded by key_encoding if it
+    //doesn't exist. An existing file keeps the encoding it was created with.
+    //Scans return keys as they are stored, i.e. encoded
+    pub fn open_with_encoding(path: impl AsRef<Path>, key_encoding: KeyEncoding) -> Result<KV> {
+        Ok(KV {
+            tree: BTree::open(path, key_encoding, DEFAULT_PAGE_SIZE)?,
+            poisoned: false,
+            #[cfg(feature = "latency-histograms")]
+            latency: RefCell::new(Latency::new()),
+        })
+    }
+
+    //Open the store like open, then run a bounded integrity check of the file and refuse to
+    //open it with DbError::SelfTestFailed when the check finds damage
+    pub fn open_with_selftest(path: impl AsRef<Path>) -> Result<KV> {
+        let kv = KV::open(path)?;
+        selftest::self_test(&kv.tree, SELF_TEST_DESCENTS).map_err(DbError::SelfTestFailed)?;
+        Ok(kv)
+    }
+
+    //Open the store like open, logging updates in a write-ahead log next to the file instead
+    //of syncing the file on every update, see wal
+    pub fn open_with_wal(path: impl AsRef<Path>) -> Result<KV> {
+        let mut kv = KV::open(path)?;
+        kv.pager_mut().enable_wal()?;
+        Ok(kv)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check()?;
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.tree.get(key);
+        #[cfg(feature = "latency-histograms")]
+        self.record(Operation::Get, start);
+        result
+    }
+
+    //Store the value for key, replacing the previous one
+    pub fn set(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.update(|tree| {
+            tree.insert(key, val)?;
+            tree.flush()
+        });
+        #[cfg(feature = "latency-histograms")]
+        self.record(Operation::Put, start);
+        result
+    }
+
+    //Delete key, returns whether it was present
+    pub fn del(&mut self, key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.update(|tree| {
+            let deleted = tree.delete(key)?;
+            if deleted {
+                tree.flush()?;
+            }
+            Ok(deleted)
+        });
+        #[cfg(feature = "latency-histograms")]
+        self.record(Operation::Del, start);
+        result
+    }
+
+    //Start a read transaction. It borrows the store, so no update can happen while it is
+    //alive and every read sees the tree as of this call
+    pub fn begin_read(&self) -> Result<ReadTx<'_>> {
+        self.check()?;
+        Ok(ReadTx { kv: self })
+    }
+
+    //Start a write transaction. Its updates are visible to its own reads right away but only
+    //become durable on commit, a transaction dropped without commit is rolled back
+    pub fn begin_write(&mut self) -> Result<WriteTx<'_>> {
+        self.check()?;
+        Ok(WriteTx {
+            kv: self,
+            finished: false,
+        })
+    }
+
+    //Close the database file. All updates are durable already, so this only reports whether
+    //the store was poisoned, in which case the last failed update is lost, and checkpoints
+    //the write-ahead log so the next open has nothing to replay
+    pub fn close(mut self) -> Result<()> {
+        self.check()?;
+        self.pager_mut().checkpoint()
+    }
+
+    //Sync the file and empty the write-ahead log, so opening the file has nothing to replay.
+    //Nothing to do without the log
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.check()?;
+        self.pager_mut().checkpoint()
+    }
+
+    //Set how many decoded pages are kept in memory, 0 disables the page cache
+    pub fn set_cache_size(&mut self, pages: usize) {
+        self.pager_mut().set_cache_size(pages);
+    }
+
+    //Set how many bytes the file grows by at least when it runs out of pages, see
+    //Pager::set_extent_size
+    pub fn set_extent_size(&mut self, extent_size: u64) {
+        self.pager_mut().set_extent_size(extent_size);
+    }
+
+    //Hits and misses of the page cache since the store was opened
+    pub fn cache_stats(&self) -> CacheStats {
+        self.pager().cache_stats()
+    }
+
+    //Latency percentiles of every operation type since the store was opened
+    #[cfg(feature = "latency-histograms")]
+    pub fn latency_stats(&self) -> LatencyStats {
+        self.latency.borrow().stats()
+    }
+
+    #[cfg(feature = "latency-histograms")]
+    fn record(&self, operation: Operation, start: Instant) {
+        self.latency.borrow_mut().record(operation, start);
+    }
+
+    //Iterate over the entries between start and end
+    fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Scan<'_>> {
+        Ok(Scan {
+            iter: self.tree.scan(start, end)?,
+            #[cfg(feature = "latency-histograms")]
+            kv: self,
+        })
+    }
+
+    //Iterate over the entries between start and end from the last one to the first
+    fn scan_rev(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Scan<'_>> {
+        Ok(Scan {
+            iter: self.tree.scan_rev(start, end)?,
+            #[cfg(feature = "latency-histograms")]
+            kv: self,
+        })
+    }
+
+    pub(crate) fn pager(&self) -> &Pager {
+        self.tree.pages()
+    }
+
+    pub(crate) fn pager_mut(&mut self) -> &mut Pager {
+        self.tree.pages_mut()
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.poisoned {
+            return Err(DbError::Poisoned);
+        }
+        Ok(())
+    }
+
+    //Run an update, poisoning the store when it fails after the tree was touched.
+    //The store counts as poisoned while the update runs, so it stays poisoned if op panics.
+    //Invalid keys are rejected before anything is changed
+    fn update<R>(&mut self, op: impl FnOnce(&mut BTree<Pager>) -> Result<R>) -> Result<R> {
+        self.check()?;
+        self.poisoned = true;
+        let result = op(&mut self.tree);
+        self.poisoned = matches!(&result, Err(err)
+            if !matches!(err, DbError::EmptyKey | DbError::KeyTooLarge { .. }));
+        result
+    }
+}
+
+//Read-only view of the store pinned at the root it had when the transaction started
+pub struct ReadTx<'a> {
+    kv: &'a KV,
+}
+
+impl<'a> ReadTx<'a> {
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.kv.get(key)
+    }
+
+    pub fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Scan<'a>> {
+        self.kv.scan(start, end)
+    }
+
+    //Same range as scan, yielded in descending key order
+    pub fn scan_rev(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Scan<'a>> {
+        self.kv.scan_rev(start, end)
+    }
+}
+
+//Updates applied to the tree in memory, flushed together by commit.
+//Pages written by the transaction are only referenced by its own root, so rolling back just
+//returns to the last flushed root and hands the pages out again
+pub struct WriteTx<'a> {
+    kv: &'a mut KV,
+    //Set once the transaction is committed or rolled back
+    finished: bool,
+}
+
+impl WriteTx<'_> {
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.kv.tree.get(key);
+        #[cfg(feature = "latency-histograms")]
+        self.kv.record(Operation::Get, start);
+        result
+    }
+
+    pub fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Scan<'_>> {
+        self.kv.scan(start, end)
+    }
+
+    //Same range as scan, yielded in descending key order
+    pub fn scan_rev(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Scan<'_>> {
+        self.kv.scan_rev(start, end)
+    }
+
+    pub fn set(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.kv.update(|tree| tree.insert(key, val));
+        #[cfg(feature = "latency-histograms")]
+        self.kv.record(Operation::Put, start);
+        result
+    }
+
+    //Delete key, returns whether it was present
+    pub fn del(&mut self, key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.kv.update(|tree| tree.delete(key));
+        #[cfg(feature = "latency-histograms")]
+        self.kv.record(Operation::Del, start);
+        result
+    }
+
+    //Make all updates of the transaction durable at once
+    pub fn commit(mut self) -> Result<()> {
+        self.finished = true;
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.kv.update(|tree| tree.flush());
+        #[cfg(feature = "latency-histograms")]
+        self.kv.record(Operation::Commit, start);
+        result
+    }
+
+    //Drop all updates of the transaction
+    pub fn abort(mut self) -> Result<()> {
+        self.finished = true;
+        self.kv.update(|tree| tree.rollback())
+    }
+}
+
+impl Drop for WriteTx<'_> {
+    fn drop(&mut self) {
+        //There is no one to report an error to, a failed rollback poisons the store instead
+        if !self.finished && self.kv.tree.rollback().is_err() {
+            self.kv.poisoned = true;
+        }
+    }
+}
+
+//Entries of a key range in key order, see BTree::scan
+pub struct Scan<'a> {
+    iter: BTreeIter<'a, Pager>,
+    #[cfg(feature = "latency-histograms")]
+    kv: &'a KV,
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let next = self.iter.next();
+        #[cfg(feature = "latency-histograms")]
+        self.kv.record(Operation::ScanStep, start);
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn key(idx: usize) -> Vec<u8> {
+        format!("key{:04}", idx).into_bytes()
+    }
+
+    //Store with keys 0..count holding 200 byte values, spread over several leaves
+    fn filled(path: &TempPath, count: usize) -> KV {
+        let mut kv = KV::open(path).unwrap();
+        let mut tx = kv.begin_write().unwrap();
+        for idx in 0..count {
+            tx.set(&key(idx), &[idx as u8; 200]).unwrap();
+        }
+        tx.commit().unwrap();
+        kv
+    }
+
+    fn leaf_nodes(tree: &BTree<Pager>) -> u64 {
+        tree.check().unwrap().leaf_nodes
+    }
+
+    //Panic inside an update after op changed the tree, then check the store is poisoned and
+    //reopening it gives back the count keys committed before
+    fn check_panic_poisons(
+        path: &TempPath,
+        mut kv: KV,
+        count: usize,
+        op: impl FnOnce(&mut BTree<Pager>),
+    ) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            kv.update(|tree| -> Result<()> {
+                op(tree);
+                panic!("update interrupted");
+            })
+        }));
+        assert!(result.is_err());
+
+        assert!(matches!(kv.get(&key(0)), Err(DbError::Poisoned)));
+        assert!(matches!(kv.set(&key(0), b"val"), Err(DbError::Poisoned)));
+        assert!(matches!(kv.del(&key(0)), Err(DbError::Poisoned)));
+        assert!(matches!(kv.begin_write(), Err(DbError::Poisoned)));
+        assert!(matches!(kv.close(), Err(DbError::Poisoned)));
+
+        let kv = KV::open(path).unwrap();
+        let entries: Vec<_> = kv
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let expected: Vec<_> = (0..count)
+            .map(|idx| (key(idx), vec![idx as u8; 200]))
+            .collect();
+        assert!(entries == expected, "entries after reopening");
+        kv.tree.check().unwrap();
+    }
+
+    #[test]
+    fn panic_during_split_poisons() {
+        let path = TempPath::new("kv-panic-split");
+        let kv = filled(&path, 100);
+        check_panic_poisons(&path, kv, 100, |tree| {
+            let leaves = leaf_nodes(tree);
+            for idx in 100..200 {
+                tree.insert(&key(idx), &[0; 200]).unwrap();
+            }
+            assert!(leaf_nodes(tree) > leaves, "no leaf was split");
+        });
+    }
+
+    #[test]
+    fn panic_during_merge_poisons() {
+        let path = TempPath::new("kv-panic-merge");
+        let kv = filled(&path, 100);
+        check_panic_poisons(&path, kv, 100, |tree| {
+            let leaves = leaf_nodes(tree);
+            for idx in 10..100 {
+                assert!(tree.delete(&key(idx)).unwrap());
+            }
+            assert!(leaf_nodes(tree) < leaves, "no leaves were merged");
+        });
+    }
+
+    #[cfg(feature = "latency-histograms")]
+    #[test]
+    fn deletes_are_recorded_apart_from_puts() {
+        let path = TempPath::new("kv-latency-del");
+        let mut kv = KV::open(&path).unwrap();
+        kv.set(b"a", b"1").unwrap();
+        kv.set(b"b", b"2").unwrap();
+        kv.del(b"a").unwrap();
+        let mut tx = kv.begin_write().unwrap();
+        tx.del(b"b").unwrap();
+        tx.commit().unwrap();
+
+        let stats = kv.latency_stats();
+        assert_eq!(stats.put.count, 2);
+        assert_eq!(stats.del.count, 2);
+        assert_eq!(stats.commit.count, 1);
+    }
+
+    #[test]
+    fn page_cache_can_be_resized() {
+        let path = TempPath::new("kv-cache");
+        let mut kv = filled(&path, 100);
+        kv.get(&key(1)).unwrap();
+        let before = kv.cache_stats();
+        kv.get(&key(1)).unwrap();
+        let after = kv.cache_stats();
+        assert!(after.hits > before.hits);
+        assert_eq!(after.misses, before.misses);
+
+        //Without a cache every read misses
+        kv.set_cache_size(0);
+        kv.get(&key(1)).unwrap();
+        let uncached = kv.cache_stats();
+        assert_eq!(uncached.hits, after.hits);
+        assert!(uncached.misses > after.misses);
+    }
+
+    #[test]
+    fn scan_rev_in_transactions() {
+        let path = TempPath::new("kv-scan-rev");
+        let mut kv = filled(&path, 50);
+        let keys =
+            |scan: Scan<'_>| -> Vec<Vec<u8>> { scan.map(|entry| entry.unwrap().0).collect() };
+
+        let tx = kv.begin_read().unwrap();
+        let found = keys(
+            tx.scan_rev(Bound::Excluded(&key(10)), Bound::Included(&key(14)))
+                .unwrap(),
+        );
+        assert_eq!(found, [key(14), key(13), key(12), key(11)]);
+
+        //Uncommitted updates are part of the transaction's scans
+        let mut tx = kv.begin_write().unwrap();
+        tx.del(&key(49)).unwrap();
+        tx.set(b"zzz", b"last").unwrap();
+        let found = keys(tx.scan_rev(Bound::Unbounded, Bound::Unbounded).unwrap());
+        assert_eq!(found.len(), 50);
+        assert_eq!(found[..2], [b"zzz".to_vec(), key(48)]);
+        assert_eq!(found[49], key(0));
+    }
+
+    #[test]
+    fn keys_normalizing_equal_are_one_entry() {
+        let path = TempPath::new("kv-encoding");
+        let mut kv = KV::open_with_encoding(&path, KeyEncoding::UnicodeCaseFold).unwrap();
+        kv.set("Straße".as_bytes(), b"first").unwrap();
+        kv.set("STRAßE".as_bytes(), b"second").unwrap();
+        kv.set("ÄPFEL".as_bytes(), b"apples").unwrap();
+        kv.del("äpfel".as_bytes()).unwrap();
+        assert_eq!(
+            kv.get("straße".as_bytes()).unwrap(),
+            Some(b"second".to_vec())
+        );
+        let entries: Vec<_> = kv
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            entries,
+            [("straße".as_bytes().to_vec(), b"second".to_vec())]
+        );
+        kv.close().unwrap();
+
+        //The encoding is part of the file, opening it without one keeps folding keys
+        let kv = KV::open(&path).unwrap();
+        assert_eq!(
+            kv.get("StraßE".as_bytes()).unwrap(),
+            Some(b"second".to_vec())
+        );
+        assert_eq!(kv.get("ÄPFEL".as_bytes()).unwrap(), None);
+    }
+
+    #[test]
+    fn file_grows_by_the_extent_size() {
+        let path = TempPath::new("kv-extent");
+        let mut kv = filled(&path, 100);
+        let extent = 8 << 20;
+        kv.set_extent_size(extent);
+        let before = std::fs::metadata(&path).unwrap().len();
+        let mut idx = 100;
+        let mut after = before;
+        while after == before {
+            let mut tx = kv.begin_write().unwrap();
+            for _ in 0..100 {
+                tx.set(&key(idx), &[idx as u8; 200]).unwrap();
+                idx += 1;
+            }
+            tx.commit().unwrap();
+            after = std::fs::metadata(&path).unwrap().len();
+        }
+        assert!(after >= before + extent, "{} to {}", before, after);
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn collated_keys_scan_as_sort_keys() {
+        use crate::collation::{Collator, Strength};
+
+        let path = TempPath::new("kv-collated");
+        let encoding = KeyEncoding::Collated(Strength::Secondary);
+        let mut kv = KV::open_with_encoding(&path, encoding).unwrap();
+        for (key, val) in [
+            ("Zebra", "z"),
+            ("éclair", "e"),
+            ("apple", "a"),
+            ("Éclair", "E"),
+        ] {
+            kv.set(key.as_bytes(), val.as_bytes()).unwrap();
+        }
+        assert_eq!(kv.get("ÉCLAIR".as_bytes()).unwrap(), Some(b"E".to_vec()));
+
+        //Entries come back in collation order, keyed by sort key instead of the text set
+        let collator = Collator::new(Strength::Secondary);
+        let entries: Vec<_> = kv
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let expected: Vec<_> = [("apple", "a"), ("éclair", "E"), ("zebra", "z")]
+            .iter()
+            .map(|(key, val)| (collator.sort_key(key), val.as_bytes().to_vec()))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+}
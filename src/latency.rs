@@ -0,0 +1,243 @@
+use std::time::{Duration, Instant};
+
+//Latency histograms of store operations, kept so percentiles can be read from the store
+//instead of timing every call from the outside.
+//Histograms are log-linear like HDR histograms: latencies below 64ns get a bucket each, every
+//higher power of two is split into 32 buckets, so a recorded latency is off by at most 1/32
+//while a histogram covering everything up to centuries takes a fixed 1920 buckets
+
+//Latencies below this many nanoseconds are counted exactly
+const LINEAR: u64 = 64;
+//Buckets every power of two from LINEAR on is split into
+const SUB_BUCKETS: u64 = 32;
+const BUCKETS: usize = (LINEAR + 58 * SUB_BUCKETS) as usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Get,
+    //Single key set. Updates outside of a write transaction include the flush making them
+    //durable
+    Put,
+    //Single key del, timed like Put
+    Del,
+    //Flush of a write transaction
+    Commit,
+    //Fetching the next entry of a scan
+    ScanStep,
+}
+
+//Latencies of every operation type since the store was opened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub get: Percentiles,
+    pub put: Percentiles,
+    pub del: Percentiles,
+    pub commit: Percentiles,
+    pub scan_step: Percentiles,
+}
+
+//Summary of a histogram. Percentiles are the upper end of the bucket they fall in, all fields
+//are zero while nothing was recorded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+}
+
+pub(crate) struct Latency {
+    get: Histogram,
+    put: Histogram,
+    del: Histogram,
+    commit: Histogram,
+    scan_step: Histogram,
+}
+
+impl Latency {
+    pub fn new() -> Latency {
+        Latency {
+            get: Histogram::new(),
+            put: Histogram::new(),
+            del: Histogram::new(),
+            commit: Histogram::new(),
+            scan_step: Histogram::new(),
+        }
+    }
+
+    //Record an operation which started at start and just finished
+    pub fn record(&mut self, operation: Operation, start: Instant) {
+        let nanos = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        match operation {
+            Operation::Get => self.get.record(nanos),
+            Operation::Put => self.put.record(nanos),
+            Operation::Del => self.del.record(nanos),
+            Operation::Commit => self.commit.record(nanos),
+            Operation::ScanStep => self.scan_step.record(nanos),
+        }
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        LatencyStats {
+            get: self.get.percentiles(),
+            put: self.put.percentiles(),
+            del: self.del.percentiles(),
+            commit: self.commit.percentiles(),
+            scan_step: self.scan_step.percentiles(),
+        }
+    }
+}
+
+//Number of latencies recorded per bucket, in nanoseconds
+struct Histogram {
+    buckets: Box<[u64; BUCKETS]>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            buckets: Box::new([0; BUCKETS]),
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    fn record(&mut self, nanos: u64) {
+        self.buckets[bucket(nanos)] += 1;
+        self.count += 1;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    fn percentiles(&self) -> Percentiles {
+        if self.count == 0 {
+            return Percentiles::default();
+        }
+        Percentiles {
+            count: self.count,
+            min: Duration::from_nanos(self.min),
+            max: Duration::from_nanos(self.max),
+            p50: self.percentile(0.5),
+            p90: self.percentile(0.9),
+            p99: self.percentile(0.99),
+            p999: self.percentile(0.999),
+        }
+    }
+
+    //Smallest latency at least a fraction q of all recorded ones are at or below, up to the
+    //precision of the buckets
+    fn percentile(&self, q: f64) -> Duration {
+        let rank = ((q * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (idx, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_end(idx).clamp(self.min, self.max));
+            }
+        }
+        Duration::from_nanos(self.max)
+    }
+}
+
+//Bucket a latency falls in
+fn bucket(nanos: u64) -> usize {
+    if nanos < LINEAR {
+        return nanos as usize;
+    }
+    //Shift bringing the latency down to 32..64, which picks the bucket within its power of two
+    let shift = 63 - nanos.leading_zeros() as u64 - 5;
+    (LINEAR + (shift - 1) * SUB_BUCKETS + (nanos >> shift) - SUB_BUCKETS) as usize
+}
+
+//Largest latency falling in bucket idx
+fn bucket_end(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < LINEAR {
+        return idx;
+    }
+    let shift = (idx - LINEAR) / SUB_BUCKETS + 1;
+    let start = (SUB_BUCKETS + (idx - LINEAR) % SUB_BUCKETS) << shift;
+    start + ((1 << shift) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_ends_round_trip() {
+        for idx in 0..BUCKETS {
+            let end = bucket_end(idx);
+            assert_eq!(bucket(end), idx);
+            //The next latency starts the next bucket
+            if idx + 1 < BUCKETS {
+                assert_eq!(bucket(end + 1), idx + 1);
+            }
+        }
+        assert_eq!(bucket_end(BUCKETS - 1), u64::MAX);
+    }
+
+    #[test]
+    fn buckets_are_exact_below_linear_and_within_a_32th_above() {
+        for nanos in 0..LINEAR {
+            assert_eq!(bucket_end(bucket(nanos)), nanos);
+        }
+        for shift in 0..58 {
+            for nanos in [
+                LINEAR << shift,
+                (LINEAR << shift) + 12345 % (LINEAR << shift),
+            ] {
+                let end = bucket_end(bucket(nanos));
+                assert!(end >= nanos);
+                assert!(end - nanos <= nanos / SUB_BUCKETS, "{} in {}", nanos, end);
+            }
+        }
+    }
+
+    #[test]
+    fn percentiles_are_accurate() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentiles(), Percentiles::default());
+        //1us to 1ms, each latency once
+        for micros in 1..=1000 {
+            histogram.record(micros * 1000);
+        }
+
+        let percentiles = histogram.percentiles();
+        assert_eq!(percentiles.count, 1000);
+        assert_eq!(percentiles.min, Duration::from_micros(1));
+        assert_eq!(percentiles.max, Duration::from_millis(1));
+        for (found, expected) in [
+            (percentiles.p50, 500),
+            (percentiles.p90, 900),
+            (percentiles.p99, 990),
+            (percentiles.p999, 999),
+        ] {
+            let expected = Duration::from_micros(expected);
+            assert!(found >= expected, "{:?} below {:?}", found, expected);
+            assert!(
+                found <= expected + expected / 32,
+                "{:?} above {:?}",
+                found,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn single_latency_is_every_percentile() {
+        let mut histogram = Histogram::new();
+        histogram.record(1_000_003);
+        let percentiles = histogram.percentiles();
+        for found in [percentiles.p50, percentiles.p999, percentiles.min] {
+            assert_eq!(found, Duration::from_nanos(1_000_003));
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//Key-value store and table layer kept in a single database file.
+//KV is the store for one writer, SharedKV shares it between threads with snapshot reads,
+//Db keeps typed tables with secondary indexes on top of a KV and sql runs statements on them
+
+mod b_node;
+mod cache;
+mod check;
+mod checksum;
+#[cfg(feature = "collation")]
+mod collation;
+mod compare;
+mod error;
+#[cfg(test)]
+mod format_fixtures;
+mod free_list;
+mod key_encoding;
+pub mod keys;
+mod kv;
+#[cfg(feature = "latency-histograms")]
+mod latency;
+mod maintenance;
+#[cfg(test)]
+mod mem_tree;
+mod meta;
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod mmap;
+mod overflow;
+mod page_view;
+mod pager;
+mod preallocate;
+mod rng;
+mod scrub;
+mod selftest;
+mod shared;
+pub mod sql;
+mod table;
+mod temp;
+#[cfg(test)]
+mod test_util;
+mod wal;
+
+pub use crate::cache::CacheStats;
+pub use crate::check::{FileCheck, TreeStats, Violation, check_file};
+#[cfg(feature = "collation")]
+pub use crate::collation::{Collator, Strength};
+pub use crate::error::{DbError, Result};
+pub use crate::key_encoding::KeyEncoding;
+pub use crate::kv::{KV, ReadTx, Scan, WriteTx};
+#[cfg(feature = "latency-histograms")]
+pub use crate::latency::{LatencyStats, Percentiles};
+pub use crate::maintenance::{
+    CustomJob, Job, JobStats, Maintenance, MaintenanceOptions, Policy, Schedule, Weekday,
+};
+pub use crate::scrub::{ScrubOptions, Scrubber};
+pub use crate::shared::{SharedKV, SharedOptions, Snapshot};
+pub use crate::table::{Column, ColumnType, Db, IndexDef, IndexRows, Rows, TableDef};
+pub use crate::temp::{TempFile, TempSpace};
@@ -1,5 +1,144 @@
-mod b_node;
+use database::keys::Value;
+use database::sql::{self, Output};
+use database::{Db, check_file};
+use std::io::BufRead;
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() {
-    println!("Hello, world!");
+//Command line tool for database files:
+//  db check <file>    verify the tree in the file and print its statistics
+//  db sql <file>      run the SQL statements read from stdin on the tables in the file, after
+//                     a quick self-test of the file
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["check", path] => check(Path::new(path)),
+        ["sql", path] => sql(Path::new(path)),
+        _ => {
+            eprintln!("usage: db check <file>");
+            eprintln!("       db sql <file>");
+            ExitCode::from(2)
+        }
+    }
+}
+
+//Check the tree in the file at path, it fails when the file can't be opened or the tree has
+//any violation
+fn check(path: &Path) -> ExitCode {
+    let checked = match check_file(path) {
+        Ok(checked) => checked,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if checked.unreplayed_log {
+        println!(
+            "{}: the write-ahead log holds updates not replayed into the file yet",
+            path.display()
+        );
+    }
+    match checked.result {
+        Ok(stats) => {
+            let pages = stats.internal_nodes + stats.leaf_nodes;
+            println!("{}: ok", path.display());
+            println!("  keys            {}", stats.keys);
+            println!("  depth           {}", stats.depth);
+            println!("  internal nodes  {}", stats.internal_nodes);
+            println!("  leaf nodes      {}", stats.leaf_nodes);
+            println!("  overflow pages  {}", stats.overflow_pages);
+            if pages > 0 {
+                let page_size = checked.page_size as u64;
+                println!(
+                    "  node fill       {}%",
+                    stats.used_bytes * 100 / (pages * page_size)
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(violations) => {
+            for violation in &violations {
+                println!("{}: {}", path.display(), violation);
+            }
+            println!("{}: {} violations", path.display(), violations.len());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+//Run the statements read from stdin, each one ending with a semicolon at the end of a line.
+//A failed statement is reported and the next one runs anyway, the exit code tells whether any
+//of them failed
+fn sql(path: &Path) -> ExitCode {
+    let mut db = match Db::open_with_selftest(path) {
+        Ok(db) => db,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut failed = false;
+    let mut statement = String::new();
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                eprintln!("stdin: {}", err);
+                return ExitCode::FAILURE;
+            }
+            None => break,
+        };
+        statement.push_str(&line);
+        statement.push('\n');
+        if !line.trim_end().ends_with(';') {
+            continue;
+        }
+
+        match sql::execute(&mut db, &statement) {
+            Ok(Output::Done) => println!("ok"),
+            Ok(Output::Changed(rows)) => println!("{} rows changed", rows),
+            Ok(Output::Rows { columns, rows }) => {
+                println!("{}", columns.join(" | "));
+                for row in &rows {
+                    let values: Vec<_> = row.iter().map(format_value).collect();
+                    println!("{}", values.join(" | "));
+                }
+                println!("({} rows)", rows.len());
+            }
+            Err(err) => {
+                eprintln!("error: {}", err);
+                failed = true;
+            }
+        }
+        statement.clear();
+    }
+    if !statement.trim().is_empty() {
+        eprintln!("error: statement without a closing semicolon");
+        failed = true;
+    }
+
+    if let Err(err) = db.close() {
+        eprintln!("{}: {}", path.display(), err);
+        failed = true;
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Bytes(value) => {
+            let digits: String = value.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("x'{}'", digits)
+        }
+        Value::Str(value) => value.clone(),
+    }
 }
@@ -0,0 +1,430 @@
+use crate::check::Violation;
+use crate::error::Result;
+use crate::rng::Rng;
+use crate::scrub::scrub_pass;
+use crate::shared::SharedKV;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//Maintenance scheduler running periodic jobs on a store in the background, configured as a
+//list of policies: what to run and when, e.g. a scrub every Sunday at 03:00.
+//All jobs run one after the other on a single thread, so no two jobs ever overlap, a job due
+//while another one runs waits for it to finish. Occurrences a job misses because it or an
+//earlier job was still running are skipped instead of being run back to back afterwards.
+//Every run is delayed by a random jitter of up to the policy's jitter, so many stores started
+//with the same policies don't all hit their disks at the same moment.
+//Times of day are UTC. Jobs the store doesn't have built in, e.g. a compaction, are custom
+//jobs. A store opened with SharedKV::open_with gets its policies from SharedOptions
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+//When a job runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    //Repeatedly with this pause between the starts of two runs, the first one after a pause
+    Every(Duration),
+    //Every day at hour:minute UTC
+    Daily {
+        hour: u32,
+        minute: u32,
+    },
+    //Every week on day at hour:minute UTC
+    Weekly {
+        day: Weekday,
+        hour: u32,
+        minute: u32,
+    },
+}
+
+//Job run with the store it maintains, an error counts as a failed run
+pub type CustomJob = Box<dyn FnMut(&SharedKV) -> Result<()> + Send>;
+
+pub enum Job {
+    //One scrubber pass over a snapshot of the store with a pause before every page read, see
+    //Scrubber. on_violation is called for every violation found
+    Scrub {
+        page_pause: Duration,
+        on_violation: Box<dyn FnMut(&Violation) + Send>,
+    },
+    //Checkpoint of the write-ahead log, see SharedKV::checkpoint. Updates wait for it, but it
+    //keeps the log and the replay after a crash short when the log grows slowly
+    Checkpoint,
+    Custom(CustomJob),
+}
+
+pub struct Policy {
+    //Name the job is reported under in the stats
+    pub name: String,
+    pub schedule: Schedule,
+    //Largest random delay added to every run
+    pub jitter: Duration,
+    pub job: Job,
+}
+
+#[derive(Default)]
+pub struct MaintenanceOptions {
+    pub policies: Vec<Policy>,
+}
+
+//What a job did since the scheduler was started
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobStats {
+    pub name: String,
+    pub runs: u64,
+    //Runs which failed with an error, the store not being readable for a scrub included
+    pub failures: u64,
+    //Occurrences skipped because a run was still in progress when they were due
+    pub skipped: u64,
+    pub last_error: Option<String>,
+}
+
+//Handle of a running scheduler, it is stopped when the handle is dropped
+pub struct Maintenance {
+    //Dropping the sender wakes the scheduler up and makes it stop
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    stats: Arc<Mutex<Vec<JobStats>>>,
+}
+
+impl Maintenance {
+    //Start running the policies on a new thread
+    pub(crate) fn start(kv: SharedKV, options: MaintenanceOptions) -> Maintenance {
+        for policy in &options.policies {
+            policy.schedule.validate();
+        }
+        let (stop, stopped) = mpsc::channel();
+        let stats = Arc::new(Mutex::new(
+            options
+                .policies
+                .iter()
+                .map(|policy| JobStats {
+                    name: policy.name.clone(),
+                    ..JobStats::default()
+                })
+                .collect(),
+        ));
+        let reported = stats.clone();
+
+        let thread = thread::spawn(move || {
+            Scheduler {
+                kv,
+                policies: options.policies,
+                stopped,
+                stats: reported,
+                rng: Rng(now().as_nanos() as u64),
+            }
+            .run()
+        });
+
+        Maintenance {
+            stop: Some(stop),
+            thread: Some(thread),
+            stats,
+        }
+    }
+
+    //Stats of every policy, in the order of the policies
+    pub fn stats(&self) -> Vec<JobStats> {
+        //Jobs run without the stats locked, so a panicking job can't poison them
+        self.stats.lock().unwrap().clone()
+    }
+
+    //Stop the scheduler, waiting for a running custom job to finish. A running scrub is
+    //interrupted
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            //A panic in a job already ended the scheduler, there is nothing left to stop
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Scheduler {
+    kv: SharedKV,
+    policies: Vec<Policy>,
+    stopped: Receiver<()>,
+    stats: Arc<Mutex<Vec<JobStats>>>,
+    rng: Rng,
+}
+
+impl Scheduler {
+    fn run(mut self) {
+        let start = now();
+        //Time of the next occurrence of every policy and when it runs, jitter included
+        let mut next: Vec<(Duration, Duration)> = (0..self.policies.len())
+            .map(|idx| {
+                let due = self.policies[idx].schedule.next_after(start);
+                (due, due + self.jitter(idx))
+            })
+            .collect();
+
+        loop {
+            let Some((idx, &(due, run_at))) =
+                next.iter().enumerate().min_by_key(|(_, next)| next.1)
+            else {
+                //Nothing to run, only wait for the stop
+                let _ = self.stopped.recv();
+                return;
+            };
+            let wait = run_at.saturating_sub(now());
+            if self.stopped.recv_timeout(wait) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+
+            let Some(result) = self.run_job(idx) else {
+                return;
+            };
+
+            //Occurrences which passed while the job ran (or waited for others) are skipped
+            let finished = now();
+            let mut following = self.policies[idx].schedule.next_after(due);
+            let mut skipped = 0;
+            while following <= finished {
+                following = self.policies[idx].schedule.next_after(following);
+                skipped += 1;
+            }
+            next[idx] = (following, following + self.jitter(idx));
+
+            let mut stats = self.stats.lock().unwrap();
+            let stats = &mut stats[idx];
+            stats.runs += 1;
+            stats.skipped += skipped;
+            if let Err(err) = result {
+                stats.failures += 1;
+                stats.last_error = Some(err.to_string());
+            }
+        }
+    }
+
+    //Run the job of a policy, None when the scheduler was stopped while it ran
+    fn run_job(&mut self, idx: usize) -> Option<Result<()>> {
+        match &mut self.policies[idx].job {
+            Job::Scrub {
+                page_pause,
+                on_violation,
+            } => match scrub_pass(&self.kv, *page_pause, &self.stopped) {
+                Ok(Some(violations)) => {
+                    violations.iter().for_each(on_violation);
+                    Some(Ok(()))
+                }
+                Ok(None) => None,
+                Err(err) => Some(Err(err)),
+            },
+            Job::Checkpoint => Some(self.kv.checkpoint()),
+            Job::Custom(job) => Some(job(&self.kv)),
+        }
+    }
+
+    fn jitter(&mut self, idx: usize) -> Duration {
+        let jitter = self.policies[idx].jitter.as_nanos() as u64;
+        Duration::from_nanos(self.rng.below(jitter.saturating_add(1)))
+    }
+}
+
+impl Schedule {
+    fn validate(&self) {
+        match *self {
+            Schedule::Every(pause) => assert!(!pause.is_zero(), "pause between runs is zero"),
+            Schedule::Daily { hour, minute } | Schedule::Weekly { hour, minute, .. } => {
+                assert!(
+                    hour < 24 && minute < 60,
+                    "{}:{} is not a time of day",
+                    hour,
+                    minute
+                )
+            }
+        }
+    }
+
+    //First occurrence after time, both as time since the Unix epoch
+    fn next_after(&self, time: Duration) -> Duration {
+        let (hour, minute) = match *self {
+            Schedule::Every(pause) => return time + pause,
+            Schedule::Daily { hour, minute } | Schedule::Weekly { hour, minute, .. } => {
+                (hour, minute)
+            }
+        };
+        let days = time.as_secs() / SECS_PER_DAY;
+        let time_of_day = (hour * 60 * 60 + minute * 60) as u64;
+        let at = |day: u64| Duration::from_secs(day * SECS_PER_DAY + time_of_day);
+
+        let (first, period) = match *self {
+            Schedule::Weekly { day, .. } => {
+                //The epoch was a Thursday
+                let weekday = (days + Weekday::Thursday as u64) % 7;
+                (days + (day as u64 + 7 - weekday) % 7, 7)
+            }
+            _ => (days, 1),
+        };
+        if at(first) > time {
+            at(first)
+        } else {
+            at(first + period)
+        }
+    }
+}
+
+//Current time since the Unix epoch
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DbError;
+    use crate::shared::SharedOptions;
+    use crate::test_util::TempPath;
+    use crate::wal;
+
+    fn at(day: u64, hour: u64, minute: u64) -> Duration {
+        Duration::from_secs(day * SECS_PER_DAY + hour * 60 * 60 + minute * 60)
+    }
+
+    //Wait until the job of the only policy has run more than runs times
+    fn wait_for_runs(maintenance: &Maintenance, runs: u64) -> JobStats {
+        loop {
+            let stats = maintenance.stats().remove(0);
+            if stats.runs > runs {
+                return stats;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn every(pause: Duration, job: Job) -> MaintenanceOptions {
+        MaintenanceOptions {
+            policies: vec![Policy {
+                name: "job".to_string(),
+                schedule: Schedule::Every(pause),
+                jitter: Duration::ZERO,
+                job,
+            }],
+        }
+    }
+
+    #[test]
+    fn every_is_a_pause_after_the_time() {
+        let schedule = Schedule::Every(Duration::from_secs(90));
+        assert_eq!(
+            schedule.next_after(at(3, 4, 5)),
+            at(3, 4, 5) + Duration::from_secs(90)
+        );
+    }
+
+    #[test]
+    fn daily_is_the_next_time_of_day_strictly_after() {
+        let schedule = Schedule::Daily { hour: 3, minute: 0 };
+        let second = Duration::from_secs(1);
+        assert_eq!(schedule.next_after(at(10, 0, 0)), at(10, 3, 0));
+        assert_eq!(schedule.next_after(at(10, 3, 0) - second), at(10, 3, 0));
+        assert_eq!(schedule.next_after(at(10, 3, 0)), at(11, 3, 0));
+        assert_eq!(schedule.next_after(at(10, 23, 59)), at(11, 3, 0));
+    }
+
+    #[test]
+    fn weekly_lands_on_the_weekday() {
+        //1970-01-01, day 0, was a Thursday and 2026-10-16, day 20742, a Friday
+        let sunday = Schedule::Weekly {
+            day: Weekday::Sunday,
+            hour: 3,
+            minute: 0,
+        };
+        assert_eq!(sunday.next_after(at(0, 0, 0)), at(3, 3, 0));
+        assert_eq!(sunday.next_after(at(3, 3, 0)), at(10, 3, 0));
+        let thursday = Schedule::Weekly {
+            day: Weekday::Thursday,
+            hour: 0,
+            minute: 0,
+        };
+        assert_eq!(thursday.next_after(at(0, 0, 0)), at(7, 0, 0));
+        assert_eq!(thursday.next_after(at(6, 23, 59)), at(7, 0, 0));
+        let monday = Schedule::Weekly {
+            day: Weekday::Monday,
+            hour: 12,
+            minute: 30,
+        };
+        assert_eq!(monday.next_after(at(20742, 10, 0)), at(20745, 12, 30));
+        let friday = Schedule::Weekly {
+            day: Weekday::Friday,
+            hour: 9,
+            minute: 0,
+        };
+        assert_eq!(friday.next_after(at(20742, 10, 0)), at(20749, 9, 0));
+        assert_eq!(friday.next_after(at(20742, 8, 0)), at(20742, 9, 0));
+    }
+
+    #[test]
+    fn missed_runs_are_skipped() {
+        let path = TempPath::new("maintenance_skip");
+        let kv = SharedKV::open(&path).unwrap();
+        let job: CustomJob = Box::new(|_| {
+            thread::sleep(Duration::from_millis(35));
+            Ok(())
+        });
+        let maintenance = kv.start_maintenance(every(Duration::from_millis(10), Job::Custom(job)));
+        let stats = wait_for_runs(&maintenance, 2);
+        //Every run outlasts the three occurrences due while it runs
+        assert!(stats.skipped >= 3 * stats.runs, "{:?}", stats);
+        assert_eq!(stats.failures, 0);
+    }
+
+    #[test]
+    fn failed_runs_are_reported() {
+        let path = TempPath::new("maintenance_failure");
+        let kv = SharedKV::open(&path).unwrap();
+        let job: CustomJob = Box::new(|_| Err(DbError::Poisoned));
+        let maintenance = kv.start_maintenance(every(Duration::from_millis(5), Job::Custom(job)));
+        let stats = wait_for_runs(&maintenance, 0);
+        assert_eq!(stats.failures, stats.runs);
+        assert!(stats.last_error.is_some());
+    }
+
+    #[test]
+    fn checkpoint_job_empties_the_log() {
+        let path = TempPath::new("maintenance_checkpoint");
+        let options = SharedOptions {
+            wal: true,
+            maintenance: every(Duration::from_millis(10), Job::Checkpoint),
+        };
+        let (kv, maintenance) = SharedKV::open_with(&path, options).unwrap();
+        for i in 0..20u32 {
+            kv.set(&i.to_be_bytes(), b"value").unwrap();
+        }
+        //The run after the one in progress started after the last update
+        let runs = maintenance.stats()[0].runs;
+        let stats = wait_for_runs(&maintenance, runs + 1);
+        assert_eq!(stats.failures, 0);
+        let log = std::fs::metadata(wal::log_path(path.as_ref())).unwrap();
+        assert_eq!(log.len(), 0);
+        drop(maintenance);
+        drop(kv);
+        let kv = SharedKV::open(&path).unwrap();
+        assert_eq!(
+            kv.get(&7u32.to_be_bytes()).unwrap(),
+            Some(b"value".to_vec())
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::b_node::{BNode, BTree, DEFAULT_PAGE_SIZE, Tree};
+use crate::error::{DbError, Result};
+use crate::key_encoding::KeyEncoding;
+use crate::rng::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+//Tree store keeping every node in memory, for exercising the B-tree without a file.
+//Deleting a page which isn't stored panics, so a node released twice is caught right away
+pub struct MemTree {
+    pages: HashMap<u64, BNode>,
+    //Pointer handed out to the next new node, pointers are never reused
+    next: u64,
+    page_size: usize,
+}
+
+impl MemTree {
+    pub fn new(page_size: usize) -> MemTree {
+        MemTree {
+            pages: HashMap::new(),
+            //0 is the pointer of the empty tree
+            next: 1,
+            page_size,
+        }
+    }
+
+    //Number of nodes currently stored
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl Default for MemTree {
+    fn default() -> MemTree {
+        MemTree::new(DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl Tree for MemTree {
+    //Nodes are parsed again like ones read from a file, so a node built with a broken layout
+    //fails when it is read
+    fn get(&self, pointer: u64) -> Result<BNode> {
+        let node = self
+            .pages
+            .get(&pointer)
+            .ok_or_else(|| DbError::corruption(format!("pointer {} is not stored", pointer)))?;
+        BNode::parse(node.data()).map_err(|err| err.with_page(pointer))
+    }
+
+    fn new(&mut self, node: BNode) -> u64 {
+        let pointer = self.next;
+        self.next += 1;
+        self.pages.insert(pointer, node);
+        pointer
+    }
+
+    fn del(&mut self, pointer: u64) {
+        assert!(
+            self.pages.remove(&pointer).is_some(),
+            "page {} released but not stored",
+            pointer
+        );
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+//Run ops random inserts and deletes on a tree in a MemTree and cross-check it against a
+//BTreeMap, panicking at the first operation after which they disagree.
+//After every operation the whole tree is checked with BTree::check and every stored page has
+//to be reachable, so leaked pages are caught too.
+//The same seed always runs the same operations
+pub fn check_random_ops(seed: u64, ops: usize) {
+    let mut tree = BTree::new(0, KeyEncoding::Raw, MemTree::default());
+    let mut expected = BTreeMap::new();
+    let mut rng = Rng(seed);
+
+    for op in 0..ops {
+        //Draw keys from a small range so deletes and replacements hit existing keys
+        let key = format!("key{}", rng.below(ops as u64 / 2 + 1)).into_bytes();
+        if rng.below(3) == 0 {
+            let deleted = tree.delete(&key).unwrap();
+            assert_eq!(
+                deleted,
+                expected.remove(&key).is_some(),
+                "op {}: delete of {}",
+                op,
+                key.escape_ascii()
+            );
+        } else {
+            //Mostly small values, now and then one large enough for overflow pages
+            let length = if rng.below(20) == 0 {
+                rng.below(20_000)
+            } else {
+                rng.below(200)
+            };
+            let val = vec![rng.below(256) as u8; length as usize];
+            tree.insert(&key, &val).unwrap();
+            expected.insert(key.clone(), val);
+        }
+
+        assert_eq!(
+            tree.get(&key).unwrap().as_ref(),
+            expected.get(&key),
+            "op {}: get of {}",
+            op,
+            key.escape_ascii()
+        );
+        if let Err(err) = check_pages(&tree) {
+            panic!("op {}: {}", op, err);
+        }
+    }
+
+    let scanned: Vec<_> = tree
+        .scan(Bound::Unbounded, Bound::Unbounded)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    let wanted: Vec<_> = expected.into_iter().collect();
+    assert!(scanned == wanted, "scan after {} ops", ops);
+}
+
+//Check the tree and that the pages reachable from its root are exactly the stored ones
+fn check_pages(tree: &BTree<MemTree>) -> std::result::Result<(), String> {
+    let stats = tree.check().map_err(|violations| {
+        let reasons: Vec<_> = violations.iter().map(|v| v.to_string()).collect();
+        reasons.join(", ")
+    })?;
+
+    let reachable = stats.internal_nodes + stats.leaf_nodes + stats.overflow_pages;
+    let stored = tree.pages().len() as u64;
+    if stored != reachable {
+        return Err(format!(
+            "{} pages are stored but {} are reachable",
+            stored, reachable
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::RangeBounds;
+
+    #[test]
+    fn random_ops_match_btreemap() {
+        for seed in [1, 2, 3, 42, 0xdead_beef] {
+            check_random_ops(seed, 500);
+        }
+    }
+
+    fn key(idx: usize) -> Vec<u8> {
+        format!("key{:04}", idx).into_bytes()
+    }
+
+    //Start and end of a range
+    type Range = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+    //Every combination of included, excluded and unbounded ends at keys which are stored,
+    //missing between stored ones, and before and after all of them
+    fn bounds() -> Vec<Range> {
+        let mut ends = vec![Bound::Unbounded];
+        for idx in [0, 1, 2, 3, 501, 998, 999, 1000, 1001] {
+            ends.push(Bound::Included(key(idx)));
+            ends.push(Bound::Excluded(key(idx)));
+        }
+        let mut bounds = Vec::new();
+        for start in &ends {
+            for end in &ends {
+                bounds.push((start.clone(), end.clone()));
+            }
+        }
+        bounds
+    }
+
+    fn as_slice(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+        bound.as_ref().map(Vec::as_slice)
+    }
+
+    #[test]
+    fn scans_match_btreemap_range() {
+        let mut tree = BTree::new(0, KeyEncoding::Raw, MemTree::default());
+        let mut expected = BTreeMap::new();
+        //Even keys only, spread over many leaves
+        for idx in (2..1000).step_by(2) {
+            tree.insert(&key(idx), &[idx as u8; 50]).unwrap();
+            expected.insert(key(idx), vec![idx as u8; 50]);
+        }
+
+        for (start, end) in bounds() {
+            let range = (as_slice(&start), as_slice(&end));
+            let wanted: Vec<_> = expected
+                .iter()
+                .filter(|(key, _)| range.contains(key.as_slice()))
+                .map(|(key, val)| (key.clone(), val.clone()))
+                .collect();
+
+            let forward: Vec<_> = tree
+                .scan(range.0, range.1)
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert!(forward == wanted, "scan of {:?}..{:?}", start, end);
+
+            let backward: Vec<_> = tree
+                .scan_rev(range.0, range.1)
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            let reversed: Vec<_> = wanted.into_iter().rev().collect();
+            assert!(backward == reversed, "scan_rev of {:?}..{:?}", start, end);
+        }
+    }
+
+    #[test]
+    fn scans_of_empty_tree() {
+        let mut tree = BTree::new(0, KeyEncoding::Raw, MemTree::default());
+        assert!(
+            tree.scan_rev(Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+                .next()
+                .is_none()
+        );
+        tree.insert(b"key", b"val").unwrap();
+        tree.delete(b"key").unwrap();
+        for (start, end) in bounds() {
+            let range = (as_slice(&start), as_slice(&end));
+            assert!(tree.scan(range.0, range.1).unwrap().next().is_none());
+            assert!(tree.scan_rev(range.0, range.1).unwrap().next().is_none());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::b_node::valid_page_size;
+use crate::error::{DbError, Result};
+use crate::key_encoding::KeyEncoding;
+
+//Meta page stored at page 0, the only page which is ever overwritten in place.
+//Everything else is reached from it, so a tree update becomes visible at the moment its
+//meta page is written, after all new pages are already on disk.
+//The fields take far less than a disk sector, which is written atomically, so a crash
+//leaves either the old or the new meta page and never a mix of both.
+//
+//Meta page format:
+//| magic | root | total_pages | free_list_head | key_encoding | page_size |
+//|  16B  |  8B  |     8B      |       8B       |      1B      |    4B     |
+//Unlike other pages the meta page has no checksum trailer: it would sit at the end of the
+//page, outside of the sector which is written atomically.
+//Files of format 01 have neither a page size nor page checksums and can't be opened
+
+pub(crate) const META_MAGIC: &[u8; 16] = b"database-meta-02";
+const META_SIZE: usize = 45;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Meta {
+    //Root node of the tree, 0 when it is empty
+    pub root: u64,
+    //Number of pages in use including the meta page, pages past it are garbage left by a crash
+    pub total_pages: u64,
+    //First page of the free list, 0 when it is empty
+    pub free_list_head: u64,
+    pub key_encoding: KeyEncoding,
+    //Size of every page in the file, the meta page included
+    pub page_size: usize,
+}
+
+impl Meta {
+    //Meta of a new database file, which only contains the meta page
+    pub fn empty(key_encoding: KeyEncoding, page_size: usize) -> Meta {
+        Meta {
+            root: 0,
+            total_pages: 1,
+            free_list_head: 0,
+            key_encoding,
+            page_size,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut page = vec![0; self.page_size];
+        page[0..16].copy_from_slice(META_MAGIC);
+        page[16..24].copy_from_slice(&self.root.to_le_bytes());
+        page[24..32].copy_from_slice(&self.total_pages.to_le_bytes());
+        page[32..40].copy_from_slice(&self.free_list_head.to_le_bytes());
+        page[40] = self.key_encoding.id();
+        page[41..45].copy_from_slice(&(self.page_size as u32).to_le_bytes());
+        page
+    }
+
+    pub fn decode(page: &[u8]) -> Result<Meta> {
+        if page.len() < META_SIZE || &page[0..16] != META_MAGIC {
+            return Err(DbError::corruption("missing meta page signature").with_page(0));
+        }
+
+        let read_u64 =
+            |position: usize| u64::from_le_bytes(page[position..position + 8].try_into().unwrap());
+        let key_encoding = KeyEncoding::from_id(page[40]).ok_or_else(|| {
+            DbError::corruption(format!("unknown key encoding {}", page[40])).with_page(0)
+        })?;
+        let page_size = u32::from_le_bytes(page[41..45].try_into().unwrap()) as usize;
+        if !valid_page_size(page_size) {
+            return Err(
+                DbError::corruption(format!("invalid page size {}", page_size)).with_page(0),
+            );
+        }
+        let meta = Meta {
+            root: read_u64(16),
+            total_pages: read_u64(24),
+            free_list_head: read_u64(32),
+            key_encoding,
+            page_size,
+        };
+
+        if meta.total_pages == 0
+            || meta.root >= meta.total_pages
+            || meta.free_list_head >= meta.total_pages
+        {
+            return Err(DbError::corruption(format!(
+                "root {} or free list {} is outside of the {} pages in use",
+                meta.root, meta.free_list_head, meta.total_pages
+            ))
+            .with_page(0));
+        }
+        Ok(meta)
+    }
+}
@@ -0,0 +1,128 @@
+use std::ffi::c_void;
+use std::fs::File;
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::io::AsRawFd;
+
+//Read-only shared mapping of the database file.
+//The file is mapped in chunks which are never moved or unmapped while the mapping lives,
+//so slices handed out stay valid as the file grows: growing only adds a new chunk after
+//the last one, each chunk as large as everything mapped before it (at least 64MB).
+//Only bytes known to be written to the file are readable, touching a mapped range past the
+//end of the file would fault.
+//The module is only built for 64-bit Unix: mmap is declared here with a 64-bit offset, which
+//is off_t there but not on 32-bit targets, and a database file wouldn't fit in their address
+//space anyway. Other targets read every page with a read call.
+//Nodes are still decoded into copies of the mapped pages, see BNode::parse, the mapping
+//saves the read call and the page cache copy of the kernel but not the copy into the node.
+
+//Smallest chunk that is mapped, mappings only reserve address space so this is cheap
+const MIN_CHUNK_SIZE: usize = 64 << 20;
+
+const PROT_READ: c_int = 1;
+const MAP_SHARED: c_int = 1;
+
+unsafe extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+struct Chunk {
+    ptr: *const u8,
+    //File offset of the first byte of the chunk
+    offset: u64,
+    len: usize,
+}
+
+pub struct Mmap {
+    chunks: Vec<Chunk>,
+    //Bytes of the file covered by chunks
+    mapped: u64,
+    //Bytes at the start of the file which exist and can be read
+    readable: u64,
+}
+
+//Safety: the chunks are plain read-only memory which may be read from any thread, and only
+//extend, which takes &mut self, changes the list of them
+unsafe impl Send for Mmap {}
+
+impl Mmap {
+    pub fn new() -> Mmap {
+        Mmap {
+            chunks: Vec::new(),
+            mapped: 0,
+            readable: 0,
+        }
+    }
+
+    //Make the first size bytes of the file readable, mapping a new chunk when needed.
+    //size must not exceed the current length of the file
+    pub fn extend(&mut self, file: &File, size: u64) -> io::Result<()> {
+        while self.mapped < size {
+            let len = (self.mapped as usize).max(MIN_CHUNK_SIZE);
+            //Safety: a fresh read-only mapping of the file, the kernel picks the address
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    PROT_READ,
+                    MAP_SHARED,
+                    file.as_raw_fd(),
+                    self.mapped as i64,
+                )
+            };
+            if ptr as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            self.chunks.push(Chunk {
+                ptr: ptr as *const u8,
+                offset: self.mapped,
+                len,
+            });
+            self.mapped += len as u64;
+        }
+        self.readable = self.readable.max(size);
+        Ok(())
+    }
+
+    //Return the mapped bytes at offset..offset + len, or None when they are not readable
+    //through the mapping. Ranges crossing a chunk boundary are not served either
+    pub fn get(&self, offset: u64, len: usize) -> Option<&[u8]> {
+        if offset + len as u64 > self.readable {
+            return None;
+        }
+
+        let chunk = self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.offset <= offset && offset < chunk.offset + chunk.len as u64)?;
+        let start = (offset - chunk.offset) as usize;
+        if start + len > chunk.len {
+            return None;
+        }
+
+        //Safety: the range is inside the chunk and inside the file, and chunks stay mapped
+        //until the Mmap is dropped, which can't happen while the returned slice borrows it
+        Some(unsafe { std::slice::from_raw_parts(chunk.ptr.add(start), len) })
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        for chunk in &self.chunks {
+            //Safety: the chunk was returned by mmap with this length and no slices into it
+            //outlive self
+            unsafe {
+                munmap(chunk.ptr as *mut c_void, chunk.len);
+            }
+        }
+    }
+}
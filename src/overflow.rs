@@ -0,0 +1,62 @@
+use crate::b_node::{CHECKSUM_SIZE, valid_page_size};
+use crate::error::{DbError, Result};
+
+//Overflow pages hold values too large to be stored in a leaf. The value is split into a
+//chain of overflow pages and the leaf only keeps the length of the value, with the pointer
+//of the kv pair (always 0 for inline values) pointing to the first page of the chain.
+//
+//Overflow page format:
+//| type | size | next | payload |
+//|  2B  |  2B  |  8B  | size B  |
+//next is the following page of the chain, 0 for the last one
+
+//Page type of overflow pages, following the node types 1 and 2 and the free list type 3
+pub(crate) const OVERFLOW_TYPE: u16 = 4;
+const OVERFLOW_HEADER: usize = 12;
+
+//Number of value bytes a single overflow page holds
+pub(crate) fn capacity(page_size: usize) -> usize {
+    page_size - OVERFLOW_HEADER - CHECKSUM_SIZE
+}
+
+//Lay out one page of a chain
+pub(crate) fn encode_page(page_size: usize, next: u64, payload: &[u8]) -> Vec<u8> {
+    assert!(!payload.is_empty() && payload.len() <= capacity(page_size));
+
+    let mut page = vec![0; page_size];
+    page[0..2].copy_from_slice(&OVERFLOW_TYPE.to_le_bytes());
+    page[2..4].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    page[4..12].copy_from_slice(&next.to_le_bytes());
+    page[OVERFLOW_HEADER..OVERFLOW_HEADER + payload.len()].copy_from_slice(payload);
+    page
+}
+
+//Decode one page of a chain into the next page pointer and the part of the value it holds
+pub(crate) fn decode_page(page: &[u8]) -> Result<(u64, &[u8])> {
+    if !valid_page_size(page.len()) {
+        return Err(DbError::corruption(format!(
+            "overflow page is {} bytes, which is not a valid page size",
+            page.len()
+        )));
+    }
+
+    let page_type = u16::from_le_bytes(page[0..2].try_into().unwrap());
+    if page_type != OVERFLOW_TYPE {
+        return Err(DbError::corruption(format!(
+            "expected an overflow page, found page type {}",
+            page_type
+        )));
+    }
+    //Every page of a chain holds part of the value, so a cycle always outgrows the value length
+    let size = u16::from_le_bytes(page[2..4].try_into().unwrap()) as usize;
+    if size == 0 || size > capacity(page.len()) {
+        return Err(DbError::corruption(format!(
+            "overflow page holds {} bytes, it has to hold 1 to {}",
+            size,
+            capacity(page.len())
+        )));
+    }
+
+    let next = u64::from_le_bytes(page[4..12].try_into().unwrap());
+    Ok((next, &page[OVERFLOW_HEADER..OVERFLOW_HEADER + size]))
+}
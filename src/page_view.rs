@@ -0,0 +1,200 @@
+use crate::b_node::{BNodeType, HEADER};
+use crate::free_list::{self, FREE_LIST_TYPE};
+use crate::meta::{META_MAGIC, Meta};
+use crate::overflow::{self, OVERFLOW_TYPE};
+use std::fmt;
+
+//Read-only structured description of a raw page.
+//Decoding never trusts the bytes: every length and offset is bounds checked and
+//anything that doesn't add up is reported as Invalid instead of panicking,
+//so it can be pointed at arbitrary (possibly corrupted) pages when inspecting a file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PageView<'a> {
+    Node(NodeView<'a>),
+    //Page of the free list, with the next page of the list and the free pages it records
+    FreeList { next: u64, pointers: Vec<u64> },
+    //Page of an overflow chain, with the next page of the chain and the part of the value it holds
+    Overflow { next: u64, payload: &'a [u8] },
+    //Meta page at page 0
+    Meta(Meta),
+    Invalid { reason: String },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct NodeView<'a> {
+    pub b_type: BNodeType,
+    pub entries: Vec<EntryView<'a>>,
+    //Bytes from the start of the page to the end of the last kv pair
+    pub used_bytes: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EntryView<'a> {
+    pub ptr: u64,
+    pub key: &'a [u8],
+    pub val: &'a [u8],
+}
+
+impl<'a> PageView<'a> {
+    //Decode a page, the type stored in its first two bytes decides the layout
+    pub fn decode(page: &'a [u8]) -> PageView<'a> {
+        if page.starts_with(META_MAGIC) {
+            return match Meta::decode(page) {
+                Ok(meta) => PageView::Meta(meta),
+                Err(err) => PageView::Invalid {
+                    reason: err.to_string(),
+                },
+            };
+        }
+
+        if read_u16(page, 0) == Ok(FREE_LIST_TYPE) {
+            return match free_list::decode_page(page) {
+                Ok((next, pointers)) => PageView::FreeList { next, pointers },
+                Err(err) => PageView::Invalid {
+                    reason: err.to_string(),
+                },
+            };
+        }
+
+        if read_u16(page, 0) == Ok(OVERFLOW_TYPE) {
+            return match overflow::decode_page(page) {
+                Ok((next, payload)) => PageView::Overflow { next, payload },
+                Err(err) => PageView::Invalid {
+                    reason: err.to_string(),
+                },
+            };
+        }
+
+        match decode_node(page) {
+            Ok(node) => PageView::Node(node),
+            Err(reason) => PageView::Invalid { reason },
+        }
+    }
+}
+
+fn read_u16(page: &[u8], position: usize) -> Result<u16, String> {
+    page.get(position..position + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| format!("u16 at {} is past the end of the page", position))
+}
+
+fn read_u64(page: &[u8], position: usize) -> Result<u64, String> {
+    page.get(position..position + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| format!("u64 at {} is past the end of the page", position))
+}
+
+fn decode_node(page: &[u8]) -> Result<NodeView<'_>, String> {
+    let b_type = match read_u16(page, 0)? {
+        1 => BNodeType::InternalNode,
+        2 => BNodeType::LeafNode,
+        other => return Err(format!("unknown page type {}", other)),
+    };
+    let n_keys = read_u16(page, 2)? as usize;
+
+    let pointers_start = HEADER as usize;
+    let offsets_start = pointers_start + 8 * n_keys;
+    let kv_start = offsets_start + 2 * n_keys;
+
+    let mut entries = Vec::with_capacity(n_keys);
+    let mut offset = 0;
+    for idx in 0..n_keys {
+        let ptr = read_u64(page, pointers_start + 8 * idx)?;
+
+        let position = kv_start + offset;
+        let key_length = read_u16(page, position)? as usize;
+        let value_length = read_u16(page, position + 2)? as usize;
+        let key = page
+            .get(position + 4..position + 4 + key_length)
+            .ok_or_else(|| format!("key {} is past the end of the page", idx))?;
+        let val = page
+            .get(position + 4 + key_length..position + 4 + key_length + value_length)
+            .ok_or_else(|| format!("value {} is past the end of the page", idx))?;
+
+        //The stored offset of the next pair has to point right after this one
+        let next_offset = read_u16(page, offsets_start + 2 * idx)? as usize;
+        if next_offset != offset + 4 + key_length + value_length {
+            return Err(format!(
+                "offset {} is {} but kv pair {} ends at {}",
+                idx + 1,
+                next_offset,
+                idx,
+                offset + 4 + key_length + value_length
+            ));
+        }
+        offset = next_offset;
+
+        entries.push(EntryView { ptr, key, val });
+    }
+
+    Ok(NodeView {
+        b_type,
+        entries,
+        used_bytes: kv_start + offset,
+    })
+}
+
+impl fmt::Display for PageView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageView::Node(node) => {
+                writeln!(
+                    f,
+                    "{:?} with {} keys, {} bytes used",
+                    node.b_type,
+                    node.entries.len(),
+                    node.used_bytes
+                )?;
+                for (idx, entry) in node.entries.iter().enumerate() {
+                    write!(f, "  [{}] key={}", idx, escape(entry.key))?;
+                    match node.b_type {
+                        BNodeType::InternalNode => writeln!(f, " -> page {}", entry.ptr)?,
+                        //A leaf entry with a pointer only stores the length of a value
+                        //kept in overflow pages
+                        BNodeType::LeafNode if entry.ptr != 0 => match entry.val.try_into() {
+                            Ok(length) => writeln!(
+                                f,
+                                " {} byte value in overflow pages from page {}",
+                                u64::from_le_bytes(length),
+                                entry.ptr
+                            )?,
+                            Err(_) => writeln!(
+                                f,
+                                " val={} with overflow page {}",
+                                escape(entry.val),
+                                entry.ptr
+                            )?,
+                        },
+                        BNodeType::LeafNode => writeln!(f, " val={}", escape(entry.val))?,
+                    }
+                }
+                Ok(())
+            }
+            PageView::FreeList { next, pointers } => {
+                writeln!(f, "FreeList with {} pages, next {}", pointers.len(), next)?;
+                for ptr in pointers {
+                    writeln!(f, "  page {}", ptr)?;
+                }
+                Ok(())
+            }
+            PageView::Overflow { next, payload } => writeln!(
+                f,
+                "Overflow with {} bytes, next {}: {}",
+                payload.len(),
+                next,
+                escape(payload)
+            ),
+            PageView::Meta(meta) => writeln!(
+                f,
+                "Meta root {}, {} pages of {} bytes, free list {}, key encoding {:?}",
+                meta.root, meta.total_pages, meta.page_size, meta.free_list_head, meta.key_encoding
+            ),
+            PageView::Invalid { reason } => writeln!(f, "invalid page: {}", reason),
+        }
+    }
+}
+
+//Printable form of raw bytes
+fn escape(bytes: &[u8]) -> String {
+    bytes.escape_ascii().to_string()
+}
@@ -0,0 +1,659 @@
+use crate::b_node::{BNode, BTree, CHECKSUM_SIZE, MIN_PAGE_SIZE, Tree, valid_page_size};
+use crate::cache::{self, CacheStats, PageCache};
+use crate::checksum::crc32;
+use crate::error::{DbError, Result};
+use crate::free_list;
+use crate::key_encoding::KeyEncoding;
+use crate::meta::Meta;
+#[cfg(all(unix, target_pointer_width = "64"))]
+use crate::mmap::Mmap;
+use crate::preallocate;
+use crate::wal::{self, Wal};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+//Number of bytes the file grows by at least, unless configured otherwise
+const DEFAULT_EXTENT_SIZE: u64 = 1 << 20;
+//Number of decoded pages kept in memory, unless configured otherwise
+const DEFAULT_CACHE_PAGES: usize = 1024;
+//Size the write-ahead log grows to before a flush checkpoints it
+const WAL_CHECKPOINT_SIZE: u64 = 16 << 20;
+
+//Page store backed by a single file, page n lives at byte offset n * page size.
+//Page 0 holds the meta page and is never handed out: pointer 0 means "no page" in BTree.
+//New pages are kept in memory until flush writes them out and syncs the file, then the
+//meta page pointing to them is written and synced, so a tree update only becomes durable
+//(and visible after a reopen) once it is flushed.
+//Until then the previous meta page is the one a crash falls back to, so the pages of the
+//tree and free list it points to must stay untouched: a page released by del is held back
+//and only handed out again after the next flush.
+//Every page but the meta page ends with a CRC32 of the rest of it, written along with the
+//page and checked whenever it is read back, so a damaged page is reported as corrupted
+//instead of being decoded.
+//On 64-bit Unix flushed pages are read through a memory mapping of the file instead of a
+//read call per page, and the most recently used pages are kept decoded in a page cache.
+//The file grows in extents of a configurable size, the pages past total_pages are unused.
+//With the write-ahead log enabled a flush syncs the log instead of the file, see wal
+pub struct Pager {
+    file: File,
+    //Length of the file, it is only ever grown by flush
+    file_size: u64,
+    //Number of bytes the file grows by at least, a multiple of the page size
+    extent_size: u64,
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    mmap: Mmap,
+    //Number of pages in the file including the ones that are not flushed yet
+    total_pages: u64,
+    //Pages created since the last flush
+    pending: HashMap<u64, BNode>,
+    //Flushed pages read recently, shared with the snapshots reading older versions, see cache
+    cache: Arc<Mutex<PageCache>>,
+    //Pages which are not referenced by the last flushed meta page, new hands them out
+    free: Vec<u64>,
+    //Pages still referenced by the last flushed meta page which are free once the next
+    //flush is done: released tree pages and the pages holding the flushed free list
+    released: Vec<u64>,
+    //Meta page as of the last flush
+    meta: Meta,
+    //Size of every page in the file, fixed when the file is created
+    page_size: usize,
+    //Number of flushes since the file was opened
+    version: u64,
+    //Whether pages freed by a flush are held back instead of handed out again, see hold_released
+    holding: bool,
+    //Pages held back with the version of the flush which freed them. They are recorded in the
+    //flushed free list like every free page, but snapshots of older versions may still read them
+    held: Vec<(u64, Vec<u64>)>,
+    //Path of the write-ahead log and the log itself once enable_wal was called
+    wal_path: PathBuf,
+    wal: Option<Wal>,
+}
+
+impl Pager {
+    //Open the database file, creating it if it doesn't exist.
+    //The file is locked exclusively until the pager is dropped, a second open of it in this
+    //or another process fails with DbError::Locked instead of both writing to it.
+    //key_encoding and page_size are only recorded for a new file, an existing one keeps its own.
+    //page_size has to be a power of two between 4KB and 64KB
+    pub fn open(
+        path: impl AsRef<Path>,
+        key_encoding: KeyEncoding,
+        page_size: usize,
+    ) -> Result<Pager> {
+        if !valid_page_size(page_size) {
+            return Err(DbError::InvalidPageSize(page_size));
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        lock(&file, false)?;
+        //Updates logged before a crash have to be in the file before anything is read from it
+        let wal_path = wal::log_path(path.as_ref());
+        wal::replay(&wal_path, &mut file)?;
+
+        let mut pager = Pager::load(file, wal_path, key_encoding, page_size)?;
+        if pager.file_size == 0 {
+            pager.write_meta()?;
+            pager.file_size = page_size as u64;
+        } else {
+            //Growing the file is only synced at checkpoints with the log enabled, so a crash
+            //can leave it shorter than the pages in use, the ones never written are missing
+            let size = pager.total_pages * pager.page_size as u64;
+            if pager.file_size < size {
+                preallocate::grow(&pager.file, pager.file_size, size)?;
+                pager.file_size = size;
+            }
+        }
+        Ok(pager)
+    }
+
+    //Open an existing database file without changing it, to inspect it as it is on disk:
+    //a write-ahead log left by a crash is not replayed and a file cut short by one is not
+    //grown, its missing pages fail to read. The file is opened for reading only, so every
+    //flush fails. Any number of read-only opens share the file, but not with a regular open
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Pager> {
+        let file = OpenOptions::new().read(true).open(&path)?;
+        lock(&file, true)?;
+        let wal_path = wal::log_path(path.as_ref());
+        let pager = Pager::load(file, wal_path, KeyEncoding::Raw, MIN_PAGE_SIZE)?;
+        if pager.file_size == 0 {
+            return Err(DbError::corruption("the file is empty"));
+        }
+        Ok(pager)
+    }
+
+    //Set up the pager of an opened file, reading the meta page and free list unless the file
+    //is empty
+    fn load(
+        file: File,
+        wal_path: PathBuf,
+        key_encoding: KeyEncoding,
+        page_size: usize,
+    ) -> Result<Pager> {
+        let file_size = file.metadata()?.len();
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        let mmap = {
+            let mut mmap = Mmap::new();
+            mmap.extend(&file, file_size)?;
+            mmap
+        };
+
+        let mut pager = Pager {
+            file,
+            file_size,
+            extent_size: DEFAULT_EXTENT_SIZE.max(page_size as u64),
+            #[cfg(all(unix, target_pointer_width = "64"))]
+            mmap,
+            total_pages: 1,
+            pending: HashMap::new(),
+            cache: Arc::new(Mutex::new(PageCache::new(DEFAULT_CACHE_PAGES))),
+            free: Vec::new(),
+            released: Vec::new(),
+            meta: Meta::empty(key_encoding, page_size),
+            page_size,
+            version: 0,
+            holding: false,
+            held: Vec::new(),
+            wal_path,
+            wal: None,
+        };
+
+        if file_size > 0 {
+            //The page size is only known after the meta page is read, it fits in the
+            //smallest page there is
+            pager.meta =
+                Meta::decode(&pager.read_bytes(0, MIN_PAGE_SIZE.min(file_size as usize))?)?;
+            pager.page_size = pager.meta.page_size;
+            pager.total_pages = pager.meta.total_pages;
+            pager.load_free_list(pager.meta.free_list_head)?;
+        }
+        Ok(pager)
+    }
+
+    //Root of the tree as of the last flush
+    pub fn root(&self) -> u64 {
+        self.meta.root
+    }
+
+    pub fn key_encoding(&self) -> KeyEncoding {
+        self.meta.key_encoding
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    //Version of the last flushed tree, counting flushes since the file was opened
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    //Number of pages in the file as of the last flush
+    pub fn flushed_pages(&self) -> u64 {
+        self.meta.total_pages
+    }
+
+    //Pages the flushed free list records as free and the pages holding the list itself.
+    //Only meaningful before the first update after opening the file or flushing
+    pub(crate) fn unused_pages(&self) -> impl Iterator<Item = u64> + '_ {
+        self.free.iter().chain(&self.released).copied()
+    }
+
+    //Hold the pages freed by every flush back until release_held allows reusing them.
+    //Needed when trees of older versions are still read from the file while it is updated
+    pub fn hold_released(&mut self) {
+        self.holding = true;
+    }
+
+    //Hand out held pages again once no tree needing them is read anymore. oldest is the
+    //oldest version still being read, pages freed by a flush are part of every older version
+    pub fn release_held(&mut self, oldest: Option<u64>) {
+        let (reusable, held) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|(version, _)| oldest.is_none_or(|oldest| *version <= oldest));
+        self.held = held;
+        for (_, pages) in reusable {
+            self.free.extend(pages);
+        }
+    }
+
+    //Set how many decoded pages are kept in memory, 0 disables the cache
+    pub fn set_cache_size(&mut self, pages: usize) {
+        cache::lock(&self.cache).set_capacity(pages);
+    }
+
+    //Hits and misses of the page cache since the file was opened, for diagnostics
+    pub fn cache_stats(&self) -> CacheStats {
+        cache::lock(&self.cache).stats()
+    }
+
+    //The page cache, for readers of older versions of the tree in the same file
+    pub(crate) fn shared_cache(&self) -> Arc<Mutex<PageCache>> {
+        self.cache.clone()
+    }
+
+    //Set how many bytes the file grows by at least, rounded up to whole pages.
+    //Larger extents mean fewer, larger allocations at the cost of unused space at the end
+    pub fn set_extent_size(&mut self, extent_size: u64) {
+        self.extent_size = extent_size.max(1).next_multiple_of(self.page_size as u64);
+    }
+
+    //Log every flush from now on in the write-ahead log, see wal
+    pub fn enable_wal(&mut self) -> Result<()> {
+        if self.wal.is_none() {
+            self.wal = Some(Wal::open(&self.wal_path)?);
+        }
+        Ok(())
+    }
+
+    //Sync the file and empty the write-ahead log, nothing to do without the log
+    pub fn checkpoint(&mut self) -> Result<()> {
+        if let Some(wal) = &mut self.wal {
+            self.file.sync_data()?;
+            wal.clear()?;
+        }
+        Ok(())
+    }
+
+    //Load the free list written by a previous flush. The pages holding the list become free
+    //after the next flush, which writes the list again
+    fn load_free_list(&mut self, head: u64) -> Result<()> {
+        let mut ptr = head;
+        let mut free = Vec::new();
+        let mut holders = Vec::new();
+        while ptr != 0 {
+            //A list longer than the file has to contain a cycle
+            if ptr >= self.total_pages || (free.len() + holders.len()) as u64 >= self.total_pages {
+                return Err(DbError::corruption(format!(
+                    "free list page {} is outside of the file or part of a cycle",
+                    ptr
+                )));
+            }
+
+            let (next, pointers) = free_list::decode_page(&self.read_checked(ptr)?)
+                .map_err(|err| err.with_page(ptr))?;
+            if let Some(bad) = pointers
+                .iter()
+                .find(|p| **p == 0 || **p >= self.total_pages)
+            {
+                return Err(DbError::corruption(format!(
+                    "free list records page {} outside of the file",
+                    bad
+                ))
+                .with_page(ptr));
+            }
+
+            holders.push(ptr);
+            free.extend(pointers);
+            ptr = next;
+        }
+
+        self.free = free;
+        self.released = holders;
+        Ok(())
+    }
+
+    //Make the tree with the given root durable: write all pending pages and the free list,
+    //and only once they are durable the meta page pointing to them, see write_flushed
+    pub fn flush(&mut self, root: u64) -> Result<()> {
+        //The new list records every page free after this flush, but it can only be written
+        //to pages which are free already, the released ones are still part of the old tree
+        //Held pages are recorded too, but may still be read so they can't hold the list
+        let held: Vec<u64> = self
+            .held
+            .iter()
+            .flat_map(|(_, pages)| pages)
+            .copied()
+            .collect();
+        //A new holder is recorded in the list too, so the count is redone after adding one
+        let capacity = free_list::capacity(self.page_size);
+        while self.free.len()
+            < (self.free.len() + self.released.len() + held.len()).div_ceil(capacity + 1)
+        {
+            self.free.push(self.total_pages);
+            self.total_pages += 1;
+        }
+
+        //Grow the file before writing, so new pages land in blocks allocated in one go.
+        //Pages allocated and freed before ever being written have to exist in the file too,
+        //since the free list hands them out again
+        let size = self.total_pages * self.page_size as u64;
+        if self.file_size < size {
+            let grown = size.max(self.file_size + self.extent_size);
+            preallocate::grow(&self.file, self.file_size, grown)?;
+            self.file_size = grown;
+        }
+
+        let mut nodes: Vec<_> = self.pending.drain().collect();
+        nodes.sort_by_key(|(ptr, _)| *ptr);
+        let mut pages: Vec<_> = nodes
+            .iter()
+            .map(|(ptr, node)| (*ptr, self.seal(node.data())))
+            .collect();
+
+        let reusable = self.free.len();
+        let released = std::mem::take(&mut self.released);
+        let mut free = std::mem::take(&mut self.free);
+        free.extend(&released);
+        free.extend(&held);
+        let list = free_list::build(self.page_size, &free);
+        pages.extend(list.iter().map(|(ptr, page)| (*ptr, self.seal(page))));
+
+        let meta = Meta {
+            root,
+            total_pages: self.total_pages,
+            free_list_head: list.first().map(|(ptr, _)| *ptr).unwrap_or(0),
+            key_encoding: self.meta.key_encoding,
+            page_size: self.page_size,
+        };
+        self.write_flushed(pages, meta)?;
+
+        //The old tree is gone now, only the pages holding the new list are still in use
+        self.version += 1;
+        self.released = list.iter().map(|(ptr, _)| *ptr).collect();
+        self.free = free[list.len()..reusable].to_vec();
+        if self.holding {
+            self.held.push((self.version, released));
+        } else {
+            self.free.extend(released);
+        }
+
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        self.mmap.extend(&self.file, size)?;
+        Ok(())
+    }
+
+    //Write the pages of a flush and then the meta page pointing to them. Without the log the
+    //pages are synced before the meta page is written and synced. With it pages and meta page
+    //are synced together in the log first, the file is only synced at the next checkpoint
+    fn write_flushed(&mut self, mut pages: Vec<(u64, Vec<u8>)>, meta: Meta) -> Result<()> {
+        self.meta = meta;
+        let Some(wal) = &mut self.wal else {
+            for (ptr, page) in &pages {
+                self.write_raw(*ptr, page)?;
+            }
+            self.file.sync_data()?;
+            return self.write_meta();
+        };
+
+        pages.push((0, self.meta.encode()));
+        wal.append(self.page_size, &pages)?;
+        let checkpoint = wal.size() >= WAL_CHECKPOINT_SIZE;
+        for (ptr, page) in &pages {
+            self.write_raw(*ptr, page)?;
+        }
+        if checkpoint {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn write_meta(&mut self) -> Result<()> {
+        self.write_raw(0, &self.meta.encode())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn write_raw(&mut self, ptr: u64, page: &[u8]) -> Result<()> {
+        self.file
+            .seek(SeekFrom::Start(ptr * self.page_size as u64))?;
+        self.file.write_all(page)?;
+        Ok(())
+    }
+
+    //Forget every change since the last flush: pages created since then are dropped and the
+    //free pages are again the ones recorded by the flushed free list
+    pub fn rollback(&mut self) -> Result<()> {
+        self.pending.clear();
+        self.total_pages = self.meta.total_pages;
+        self.load_free_list(self.meta.free_list_head)?;
+
+        let held: HashSet<u64> = self
+            .held
+            .iter()
+            .flat_map(|(_, pages)| pages)
+            .copied()
+            .collect();
+        self.free.retain(|ptr| !held.contains(ptr));
+        Ok(())
+    }
+
+    //Copy of a page with the checksum of its contents in place of its last bytes
+    fn seal(&self, page: &[u8]) -> Vec<u8> {
+        let (body, _) = page.split_at(self.page_size - CHECKSUM_SIZE);
+        let mut sealed = body.to_vec();
+        sealed.extend_from_slice(&crc32(body).to_le_bytes());
+        sealed
+    }
+
+    //Read the bytes of a flushed page and check them against the checksum, they are borrowed
+    //from the mapping when it covers the page
+    fn read_checked(&self, ptr: u64) -> Result<Cow<'_, [u8]>> {
+        let page = self.read_bytes(ptr * self.page_size as u64, self.page_size)?;
+        verify_checksum(&page, ptr)?;
+        Ok(page)
+    }
+
+    fn read_bytes(&self, offset: u64, length: usize) -> Result<Cow<'_, [u8]>> {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        if let Some(bytes) = self.mmap.get(offset, length) {
+            return Ok(Cow::Borrowed(bytes));
+        }
+
+        let mut bytes = vec![0; length];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+
+    //Read and decode a flushed node
+    fn read_page(&self, ptr: u64) -> Result<BNode> {
+        BNode::parse(&self.read_checked(ptr)?).map_err(|err| err.with_page(ptr))
+    }
+}
+
+//Lock the whole file for as long as it is open, shared or exclusively: flock on Unix,
+//LockFileEx on Windows. Locks are advisory on Unix, they only keep out other opens of this code
+fn lock(file: &File, shared: bool) -> Result<()> {
+    let locked = match shared {
+        true => file.try_lock_shared(),
+        false => file.try_lock(),
+    };
+    match locked {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(DbError::Locked),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+fn verify_checksum(page: &[u8], ptr: u64) -> Result<()> {
+    let (body, trailer) = page.split_at(page.len() - CHECKSUM_SIZE);
+    let stored = u32::from_le_bytes(trailer.try_into().unwrap());
+    let computed = crc32(body);
+    if stored != computed {
+        return Err(DbError::corruption(format!(
+            "checksum is {:08x} but the page contents add up to {:08x}",
+            stored, computed
+        ))
+        .with_page(ptr));
+    }
+    Ok(())
+}
+
+//Read and decode a flushed node with a positioned read, which leaves the file cursor alone
+//so any number of threads can read through the same file
+pub(crate) fn read_page_at(file: &File, page_size: usize, ptr: u64) -> Result<BNode> {
+    let mut page = vec![0; page_size];
+    let offset = ptr * page_size as u64;
+    #[cfg(unix)]
+    std::os::unix::fs::FileExt::read_exact_at(file, &mut page, offset)?;
+    #[cfg(windows)]
+    {
+        let mut read = 0;
+        while read < page.len() {
+            match std::os::windows::fs::FileExt::seek_read(
+                file,
+                &mut page[read..],
+                offset + read as u64,
+            )? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                n => read += n,
+            }
+        }
+    }
+    //Without positioned reads every reader moves the one cursor of the file, so seeking and
+    //reading happen under a lock
+    #[cfg(not(any(unix, windows)))]
+    {
+        static CURSOR: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _cursor = CURSOR.lock().unwrap_or_else(|err| err.into_inner());
+        let mut file = file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut page)?;
+    }
+
+    verify_checksum(&page, ptr)?;
+    BNode::parse(&page).map_err(|err| err.with_page(ptr))
+}
+
+impl Tree for Pager {
+    fn get(&self, pointer: u64) -> Result<BNode> {
+        //Pointers are read from pages on disk, so a bad one means a corrupted parent
+        if pointer == 0 || pointer >= self.total_pages {
+            return Err(DbError::corruption(format!(
+                "pointer {} is outside of the {} pages in the file",
+                pointer, self.total_pages
+            )));
+        }
+
+        if let Some(node) = self.pending.get(&pointer) {
+            return Ok(node.clone());
+        }
+        //Flushed pages are read for the tree of the last flush, pending ones are not cached
+        if let Some(node) = cache::lock(&self.cache).get(pointer, self.version) {
+            return Ok(node);
+        }
+        let node = self.read_page(pointer)?;
+        cache::lock(&self.cache).insert(pointer, node.clone(), self.version);
+        Ok(node)
+    }
+
+    fn new(&mut self, node: BNode) -> u64 {
+        let pointer = self.free.pop().unwrap_or_else(|| {
+            self.total_pages += 1;
+            self.total_pages - 1
+        });
+        //A reused page gets new contents, the cached ones are stale
+        cache::lock(&self.cache).remove(pointer);
+        self.pending.insert(pointer, node);
+        pointer
+    }
+
+    fn del(&mut self, pointer: u64) {
+        debug_assert!(pointer != 0 && pointer < self.total_pages);
+
+        //A page written since the last flush isn't part of the flushed tree and can be reused
+        //right away, any other one is still referenced by the meta page
+        if self.pending.remove(&pointer).is_some() {
+            self.free.push(pointer);
+        } else {
+            self.released.push(pointer);
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+impl BTree<Pager> {
+    //Open the tree stored in the database file at path, creating the file if needed.
+    //key_encoding and page_size are only used for a new file, an existing tree keeps the
+    //ones it was created with
+    pub fn open(
+        path: impl AsRef<Path>,
+        key_encoding: KeyEncoding,
+        page_size: usize,
+    ) -> Result<BTree<Pager>> {
+        let pager = Pager::open(path, key_encoding, page_size)?;
+        Ok(BTree::new(pager.root(), pager.key_encoding(), pager))
+    }
+
+    //Open the tree of an existing database file for reading only, see Pager::open_read_only
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<BTree<Pager>> {
+        let pager = Pager::open_read_only(path)?;
+        Ok(BTree::new(pager.root(), pager.key_encoding(), pager))
+    }
+
+    //Make all updates so far durable
+    pub fn flush(&mut self) -> Result<()> {
+        let root = self.root();
+        self.pages_mut().flush(root)
+    }
+
+    //Undo all updates since the last flush
+    pub fn rollback(&mut self) -> Result<()> {
+        self.pages_mut().rollback()?;
+        let root = self.pages().root();
+        self.set_root(root);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_node::DEFAULT_PAGE_SIZE;
+    use crate::kv::KV;
+    use crate::overflow;
+    use crate::test_util::TempPath;
+
+    //Releasing one page more than a free list page holds needs a second list page, which is
+    //recorded in the list as well and has to be a new page, not one of the released ones
+    #[test]
+    fn flush_after_releasing_capacity_plus_one_pages() {
+        let path = TempPath::new("capacity-plus-one");
+        let mut kv = KV::open(&path).unwrap();
+        //A chain of as many overflow pages as a list page holds, deleting it releases the
+        //chain and the leaf
+        let length = overflow::capacity(DEFAULT_PAGE_SIZE) * free_list::capacity(DEFAULT_PAGE_SIZE);
+        kv.set(b"big", &vec![7; length]).unwrap();
+        let pages_before = kv.pager().flushed_pages();
+        kv.del(b"big").unwrap();
+        kv.close().unwrap();
+
+        let pager = Pager::open(&path, KeyEncoding::Raw, DEFAULT_PAGE_SIZE).unwrap();
+        //The list was written to new pages, the released ones were still in the old tree
+        assert!(pager.released.iter().all(|ptr| *ptr >= pages_before));
+        //Every page but the meta page and the root leaf holding the sentinel is free, and
+        //each one is recorded once
+        let unused: HashSet<u64> = pager.unused_pages().collect();
+        assert_eq!(unused.len(), pager.unused_pages().count());
+        assert!(!unused.contains(&pager.root()));
+        assert_eq!(unused.len() as u64, pager.flushed_pages() - 2);
+    }
+
+    #[test]
+    fn open_locks_the_file() {
+        let path = TempPath::new("pager-lock");
+        let kv = KV::open(&path).unwrap();
+        assert!(matches!(KV::open(&path), Err(DbError::Locked)));
+        assert!(matches!(Pager::open_read_only(&path), Err(DbError::Locked)));
+        drop(kv);
+
+        let first = Pager::open_read_only(&path).unwrap();
+        let second = Pager::open_read_only(&path).unwrap();
+        assert!(matches!(KV::open(&path), Err(DbError::Locked)));
+        drop((first, second));
+        KV::open(&path).unwrap();
+    }
+}
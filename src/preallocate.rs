@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io;
+
+//Growing the database file.
+//The file grows by whole extents instead of by the pages a flush happens to write, so it is
+//laid out in fewer, larger pieces and most flushes don't change its size, which keeps
+//fsync from also having to write out file metadata. On Linux the extent is reserved with
+//posix_fallocate so the blocks are really allocated, elsewhere the file is only extended.
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod fallocate {
+    use std::ffi::c_int;
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    unsafe extern "C" {
+        fn posix_fallocate(fd: c_int, offset: i64, len: i64) -> c_int;
+    }
+
+    //Allocate the blocks of offset..offset + len, extending the file when needed.
+    //Unlike most calls posix_fallocate returns the error number instead of setting errno
+    pub fn allocate(file: &File, offset: u64, len: u64) -> io::Result<()> {
+        //Safety: only reads the descriptor, which stays open while file is borrowed
+        let err = unsafe { posix_fallocate(file.as_raw_fd(), offset as i64, len as i64) };
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        Ok(())
+    }
+}
+
+//Grow the file from current_size bytes to size bytes
+pub fn grow(file: &File, current_size: u64, size: u64) -> io::Result<()> {
+    debug_assert!(current_size < size);
+
+    //File systems without support for it refuse the call, they just get a longer file
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    if fallocate::allocate(file, current_size, size - current_size).is_ok() {
+        return Ok(());
+    }
+
+    file.set_len(size)
+}
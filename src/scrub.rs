@@ -0,0 +1,232 @@
+use crate::b_node::{BNode, BTree, Tree};
+use crate::check::Violation;
+use crate::error::{DbError, Result};
+use crate::shared::SharedKV;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+//Background scrubber, which slowly reads the whole tree over and over to find latent
+//corruption (e.g. bad sectors) before a query runs into it.
+//Every pass checks a snapshot of the latest tree with BTree::check, so each reachable page
+//has its checksum and layout verified and the tree its cross-page invariants. Pages are read
+//from the file and not from the page cache, whose copies say nothing about the disk. Pages are read
+//one at a time with a pause in between to keep the load on the disk low. The snapshot keeps
+//the pages of its version from being reused, so a long pass makes the file grow under a
+//heavy update load; passes are meant to be paced for hours, not for seconds
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScrubOptions {
+    //Pause before every page read
+    pub page_pause: Duration,
+    //Pause between the end of a pass and the start of the next one
+    pub pass_interval: Duration,
+}
+
+impl Default for ScrubOptions {
+    fn default() -> ScrubOptions {
+        ScrubOptions {
+            page_pause: Duration::from_millis(10),
+            pass_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+//Handle of a running scrubber, it is stopped when the handle is dropped
+pub struct Scrubber {
+    //Dropping the sender wakes the scrubber up and makes it stop
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    passes: Arc<AtomicU64>,
+}
+
+impl Scrubber {
+    //Start scrubbing the store on a new thread. on_violation is called from that thread for
+    //every violation a pass finds, a store which can't be read anymore ends the scrubber
+    pub(crate) fn start(
+        kv: SharedKV,
+        options: ScrubOptions,
+        mut on_violation: impl FnMut(&Violation) + Send + 'static,
+    ) -> Scrubber {
+        let (stop, stopped) = mpsc::channel();
+        let passes = Arc::new(AtomicU64::new(0));
+        let finished = passes.clone();
+
+        let thread = thread::spawn(move || {
+            loop {
+                match scrub_pass(&kv, options.page_pause, &stopped) {
+                    Ok(Some(violations)) => violations.iter().for_each(&mut on_violation),
+                    Ok(None) | Err(_) => return,
+                }
+                finished.fetch_add(1, Ordering::Relaxed);
+                if stopped.recv_timeout(options.pass_interval) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+            }
+        });
+
+        Scrubber {
+            stop: Some(stop),
+            thread: Some(thread),
+            passes,
+        }
+    }
+
+    //Number of passes finished so far
+    pub fn passes(&self) -> u64 {
+        self.passes.load(Ordering::Relaxed)
+    }
+
+    //Stop the scrubber, waiting for the page read in progress to finish
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for Scrubber {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            //A panic in on_violation already ended the scrubber, there is nothing left to stop
+            let _ = thread.join();
+        }
+    }
+}
+
+//Check a snapshot of the latest tree, returning the violations found or None when the
+//scrubber was stopped half way
+pub(crate) fn scrub_pass(
+    kv: &SharedKV,
+    page_pause: Duration,
+    stopped: &Receiver<()>,
+) -> Result<Option<Vec<Violation>>> {
+    let mut snapshot = kv.snapshot()?;
+    snapshot.bypass_cache();
+    let tree = snapshot.tree();
+    let pages = Paced {
+        pages: tree.pages(),
+        pause: page_pause,
+        stopped,
+        interrupted: Cell::new(false),
+    };
+    let paced = BTree::new(tree.root(), tree.key_encoding(), pages);
+
+    let violations = paced.check().err().unwrap_or_default();
+    if paced.pages().interrupted.get() {
+        return Ok(None);
+    }
+    Ok(Some(violations))
+}
+
+//Pages of a tree read with a pause before each one, reads fail once the scrubber is stopped
+struct Paced<'a, T: Tree> {
+    pages: &'a T,
+    pause: Duration,
+    stopped: &'a Receiver<()>,
+    //Set once a read failed because the scrubber was stopped, the pass is meaningless then
+    interrupted: Cell<bool>,
+}
+
+impl<T: Tree> Tree for Paced<'_, T> {
+    fn get(&self, pointer: u64) -> Result<BNode> {
+        if self.interrupted.get()
+            || self.stopped.recv_timeout(self.pause) != Err(RecvTimeoutError::Timeout)
+        {
+            self.interrupted.set(true);
+            return Err(DbError::corruption("scrubber stopped"));
+        }
+        self.pages.get(pointer)
+    }
+
+    fn new(&mut self, _node: BNode) -> u64 {
+        unreachable!("scrubbing only reads")
+    }
+
+    fn del(&mut self, _pointer: u64) {
+        unreachable!("scrubbing only reads")
+    }
+
+    fn page_size(&self) -> usize {
+        self.pages.page_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::time::Instant;
+
+    fn filled(path: &TempPath) -> SharedKV {
+        let kv = SharedKV::open(path).unwrap();
+        for idx in 0..100u32 {
+            kv.set(&idx.to_be_bytes(), &[7; 200]).unwrap();
+        }
+        kv
+    }
+
+    #[test]
+    fn pass_reports_corrupted_page() {
+        let path = TempPath::new("scrub-corrupted");
+        let kv = filled(&path);
+        //Damage a leaf on disk, reading it for the sample left an intact copy in the cache
+        let snapshot = kv.snapshot().unwrap();
+        let root = snapshot.tree().root();
+        let (reached, _) = snapshot.tree().check_sampled(1, 0);
+        let leaf = reached.into_iter().find(|ptr| *ptr != root).unwrap();
+        drop(snapshot);
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(leaf * 4096 + 100)).unwrap();
+        file.write_all(b"bad sector").unwrap();
+
+        let (found, violations) = mpsc::channel();
+        let options = ScrubOptions {
+            page_pause: Duration::ZERO,
+            pass_interval: Duration::from_secs(3600),
+        };
+        let scrubber = kv.start_scrubber(options, move |violation| {
+            let _ = found.send(violation.clone());
+        });
+        let violation = violations.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(violation.page, leaf);
+        assert!(violation.reason.contains("checksum"), "{}", violation);
+        drop(scrubber);
+    }
+
+    #[test]
+    fn dropping_stops_promptly() {
+        let path = TempPath::new("scrub-stop");
+        let kv = filled(&path);
+
+        //Stopped while pausing before a page read
+        let options = ScrubOptions {
+            page_pause: Duration::from_secs(3600),
+            pass_interval: Duration::from_secs(3600),
+        };
+        let scrubber = kv.start_scrubber(options, |violation| panic!("{}", violation));
+        thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        drop(scrubber);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        //Stopped while waiting for the next pass
+        let options = ScrubOptions {
+            page_pause: Duration::ZERO,
+            ..options
+        };
+        let scrubber = kv.start_scrubber(options, |violation| panic!("{}", violation));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while scrubber.passes() == 0 {
+            assert!(Instant::now() < deadline, "no pass finished");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let start = Instant::now();
+        scrubber.stop();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
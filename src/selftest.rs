@@ -0,0 +1,147 @@
+use crate::b_node::BTree;
+use crate::check::Violation;
+use crate::pager::Pager;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Startup self-test, for deployments which would rather not start than serve from a damaged
+//file. It runs right after the file is opened and has to stay fast however large the file is,
+//so unlike BTree::check it doesn't read every page:
+//- the root is read and checked, every descent starts there
+//- a number of random paths from the root down to a leaf are checked, the seed changes with
+//  every start so restarts cover different leaves over time
+//- the free list is checked to record every page at most once, never a page the sampled
+//  paths reached and never more pages than the file has
+//The free list pages themselves were already read and validated when the file was opened
+
+//Random paths from the root to a leaf checked on every start
+pub(crate) const SELF_TEST_DESCENTS: usize = 64;
+
+//Run the self-test on a tree which was just opened, returning every problem found
+pub(crate) fn self_test(tree: &BTree<Pager>, descents: usize) -> Result<(), Vec<Violation>> {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    let (reached, mut violations) = tree.check_sampled(descents, seed);
+
+    let pager = tree.pages();
+    let mut unused = HashSet::new();
+    for page in pager.unused_pages() {
+        if !unused.insert(page) {
+            violations.push(Violation {
+                page,
+                reason: "page is recorded in the free list more than once".to_string(),
+            });
+        } else if reached.contains(&page) {
+            violations.push(Violation {
+                page,
+                reason: "page is in the free list but reachable from the root".to_string(),
+            });
+        }
+    }
+    //Page 0 holds the meta page and is neither in the tree nor free
+    if unused.len() as u64 >= pager.flushed_pages() {
+        violations.push(Violation {
+            page: 0,
+            reason: format!(
+                "free list records {} pages of a file with {}",
+                unused.len(),
+                pager.flushed_pages()
+            ),
+        });
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::b_node::{CHECKSUM_SIZE, DEFAULT_PAGE_SIZE};
+    use crate::checksum::crc32;
+    use crate::error::DbError;
+    use crate::free_list;
+    use crate::kv::KV;
+    use crate::meta::Meta;
+    use crate::test_util::TempPath;
+
+    //File with a free list left behind by deleted keys
+    fn with_free_pages(path: &TempPath) {
+        let mut kv = KV::open(path).unwrap();
+        let mut tx = kv.begin_write().unwrap();
+        for idx in 0..200u32 {
+            tx.set(&idx.to_be_bytes(), &[7; 200]).unwrap();
+        }
+        tx.commit().unwrap();
+        let mut tx = kv.begin_write().unwrap();
+        for idx in 0..150u32 {
+            tx.del(&idx.to_be_bytes()).unwrap();
+        }
+        tx.commit().unwrap();
+        kv.close().unwrap();
+    }
+
+    //Change the pages recorded by the first page of the free list, edit gets the root too.
+    //The page gets a valid checksum again, so only the self-test can tell
+    fn edit_free_list(path: &TempPath, edit: impl FnOnce(&mut Vec<u64>, u64)) {
+        let mut file = std::fs::read(path).unwrap();
+        let meta = Meta::decode(&file[..DEFAULT_PAGE_SIZE]).unwrap();
+        assert_ne!(meta.free_list_head, 0);
+        let start = meta.free_list_head as usize * DEFAULT_PAGE_SIZE;
+        let page = &mut file[start..start + DEFAULT_PAGE_SIZE];
+
+        let (next, mut pointers) = free_list::decode_page(page).unwrap();
+        assert!(!pointers.is_empty());
+        edit(&mut pointers, meta.root);
+        page.copy_from_slice(&free_list::encode_page(DEFAULT_PAGE_SIZE, next, &pointers));
+        let (body, trailer) = page.split_at_mut(DEFAULT_PAGE_SIZE - CHECKSUM_SIZE);
+        trailer.copy_from_slice(&crc32(body).to_le_bytes());
+        std::fs::write(path, file).unwrap();
+    }
+
+    fn self_test_failure(path: &TempPath) -> String {
+        match KV::open_with_selftest(path) {
+            Err(DbError::SelfTestFailed(violations)) => violations
+                .iter()
+                .map(|violation| violation.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err(err) => panic!("open failed with {}", err),
+            Ok(_) => panic!("self-test passed"),
+        }
+    }
+
+    #[test]
+    fn clean_file_passes() {
+        let path = TempPath::new("selftest-clean");
+        with_free_pages(&path);
+        let kv = KV::open_with_selftest(&path).unwrap();
+        assert_eq!(kv.get(&199u32.to_be_bytes()).unwrap(), Some(vec![7; 200]));
+        drop(kv);
+        //Rewriting the list unchanged keeps it valid
+        edit_free_list(&path, |_, _| {});
+        KV::open_with_selftest(&path).unwrap();
+    }
+
+    #[test]
+    fn reachable_free_page_fails() {
+        let path = TempPath::new("selftest-reachable");
+        with_free_pages(&path);
+        edit_free_list(&path, |pointers, root| pointers.push(root));
+        let failure = self_test_failure(&path);
+        assert!(failure.contains("reachable from the root"), "{}", failure);
+    }
+
+    #[test]
+    fn duplicated_free_page_fails() {
+        let path = TempPath::new("selftest-duplicate");
+        with_free_pages(&path);
+        edit_free_list(&path, |pointers, _| pointers.push(pointers[0]));
+        let failure = self_test_failure(&path);
+        assert!(failure.contains("more than once"), "{}", failure);
+    }
+}
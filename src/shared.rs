@@ -0,0 +1,440 @@
+use crate::b_node::{BNode, BTree, BTreeIter, Tree};
+use crate::cache::{self, CacheStats, PageCache};
+use crate::check::Violation;
+use crate::error::{DbError, Result};
+use crate::key_encoding::KeyEncoding;
+use crate::kv::KV;
+#[cfg(feature = "latency-histograms")]
+use crate::latency::{Latency, LatencyStats, Operation};
+use crate::maintenance::{Maintenance, MaintenanceOptions};
+use crate::pager;
+use crate::scrub::{ScrubOptions, Scrubber};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(feature = "latency-histograms")]
+use std::time::Instant;
+
+//KV store which can be used from many threads at once.
+//Readers work on snapshots of the last flushed tree and never wait for a writer, updates are
+//serialized by a mutex around the KV. A snapshot reads flushed pages through the page cache of
+//the pager or straight from the file, and the pages of its tree stay untouched while it is
+//alive: copy-on-write never overwrites a flushed page, and the pager holds pages freed by a
+//flush back until no snapshot of an older version is left
+#[derive(Clone)]
+pub struct SharedKV {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    writer: Mutex<KV>,
+    published: Mutex<Published>,
+    //Separate handle on the database file for snapshots, only read with positioned reads
+    file: File,
+    //Page cache of the pager, see cache for how snapshots of older versions use it
+    cache: Arc<Mutex<PageCache>>,
+    //Latencies of the gets of all snapshots, updates are timed by the writer's KV
+    #[cfg(feature = "latency-histograms")]
+    reads: Mutex<Latency>,
+    key_encoding: KeyEncoding,
+    page_size: usize,
+}
+
+//Last flushed tree, which new snapshots start from
+struct Published {
+    root: u64,
+    total_pages: u64,
+    version: u64,
+    //Number of live snapshots of every version
+    readers: BTreeMap<u64, usize>,
+}
+
+//How SharedKV::open_with opens the store
+#[derive(Default)]
+pub struct SharedOptions {
+    //Log updates in a write-ahead log instead of syncing the file on every update, see
+    //KV::open_with_wal. A Checkpoint policy keeps the log short on a schedule
+    pub wal: bool,
+    //Policies run in the background for as long as the returned Maintenance is alive
+    pub maintenance: MaintenanceOptions,
+}
+
+impl SharedKV {
+    pub fn open(path: impl AsRef<Path>) -> Result<SharedKV> {
+        SharedKV::from_kv(KV::open(path.as_ref())?, path.as_ref())
+    }
+
+    //Open the store as configured by options and start its maintenance policies
+    pub fn open_with(
+        path: impl AsRef<Path>,
+        options: SharedOptions,
+    ) -> Result<(SharedKV, Maintenance)> {
+        let kv = match options.wal {
+            true => KV::open_with_wal(path.as_ref())?,
+            false => KV::open(path.as_ref())?,
+        };
+        let kv = SharedKV::from_kv(kv, path.as_ref())?;
+        let maintenance = kv.start_maintenance(options.maintenance);
+        Ok((kv, maintenance))
+    }
+
+    //Share the store kv opened from the file at path
+    fn from_kv(mut kv: KV, path: &Path) -> Result<SharedKV> {
+        kv.pager_mut().hold_released();
+        let file = File::open(path)?;
+        let pager = kv.pager();
+        let published = Published {
+            root: pager.root(),
+            total_pages: pager.flushed_pages(),
+            version: pager.version(),
+            readers: BTreeMap::new(),
+        };
+        Ok(SharedKV {
+            shared: Arc::new(Shared {
+                key_encoding: pager.key_encoding(),
+                page_size: pager.page_size(),
+                cache: pager.shared_cache(),
+                #[cfg(feature = "latency-histograms")]
+                reads: Mutex::new(Latency::new()),
+                writer: Mutex::new(kv),
+                published: Mutex::new(published),
+                file,
+            }),
+        })
+    }
+
+    //Start reading the tree as of the last finished update
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let mut published = self.shared.published()?;
+        let version = published.version;
+        *published.readers.entry(version).or_default() += 1;
+
+        let pages = SnapshotPages {
+            shared: self.shared.clone(),
+            total_pages: published.total_pages,
+            version,
+            cached: true,
+        };
+        Ok(Snapshot {
+            version: published.version,
+            tree: BTree::new(published.root, self.shared.key_encoding, pages),
+        })
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.snapshot()?.get(key)
+    }
+
+    //Store the value for key, waiting for other updates to finish first
+    pub fn set(&self, key: &[u8], val: &[u8]) -> Result<()> {
+        self.update(|kv| kv.set(key, val))
+    }
+
+    //Delete key, returns whether it was present
+    pub fn del(&self, key: &[u8]) -> Result<bool> {
+        self.update(|kv| kv.del(key))
+    }
+
+    //Checkpoint the write-ahead log, waiting for other updates to finish first, see
+    //KV::checkpoint
+    pub fn checkpoint(&self) -> Result<()> {
+        self.update(|kv| kv.checkpoint())
+    }
+
+    //Set how many decoded pages are kept in memory, the cache is shared by the writer and
+    //all snapshots
+    pub fn set_cache_size(&self, pages: usize) {
+        cache::lock(&self.shared.cache).set_capacity(pages);
+    }
+
+    //Hits and misses of the page cache of the writer and all snapshots since the store was opened
+    pub fn cache_stats(&self) -> CacheStats {
+        cache::lock(&self.shared.cache).stats()
+    }
+
+    //Latency percentiles of every operation type since the store was opened. Gets are timed
+    //on the snapshots they read, updates by the writer, scans of snapshots are not timed
+    #[cfg(feature = "latency-histograms")]
+    pub fn latency_stats(&self) -> Result<LatencyStats> {
+        let writer = self.shared.writer.lock().map_err(|_| DbError::Poisoned)?;
+        let reads = self.shared.reads.lock().map_err(|_| DbError::Poisoned)?;
+        Ok(LatencyStats {
+            get: reads.stats().get,
+            ..writer.latency_stats()
+        })
+    }
+
+    //Start scrubbing the store in the background, see Scrubber
+    pub fn start_scrubber(
+        &self,
+        options: ScrubOptions,
+        on_violation: impl FnMut(&Violation) + Send + 'static,
+    ) -> Scrubber {
+        Scrubber::start(self.clone(), options, on_violation)
+    }
+
+    //Start running the maintenance policies in the background, see Maintenance
+    pub fn start_maintenance(&self, options: MaintenanceOptions) -> Maintenance {
+        Maintenance::start(self.clone(), options)
+    }
+
+    //Run an update under the writer lock, then publish the new tree and hand out the pages
+    //no snapshot needs anymore
+    fn update<R>(&self, op: impl FnOnce(&mut KV) -> Result<R>) -> Result<R> {
+        let mut kv = self.shared.writer.lock().map_err(|_| DbError::Poisoned)?;
+        let result = op(&mut kv);
+
+        let pager = kv.pager_mut();
+        let mut published = self.shared.published()?;
+        if pager.version() != published.version {
+            published.root = pager.root();
+            published.total_pages = pager.flushed_pages();
+            published.version = pager.version();
+        }
+        pager.release_held(published.readers.keys().next().copied());
+        result
+    }
+}
+
+impl Shared {
+    //Nothing can panic while the published state is locked, it is never poisoned in practice
+    fn published(&self) -> Result<MutexGuard<'_, Published>> {
+        self.published.lock().map_err(|_| DbError::Poisoned)
+    }
+}
+
+//Read-only view of the tree as of one update
+pub struct Snapshot {
+    version: u64,
+    tree: BTree<SnapshotPages>,
+}
+
+impl Snapshot {
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        #[cfg(feature = "latency-histograms")]
+        let start = Instant::now();
+        let result = self.tree.get(key);
+        #[cfg(feature = "latency-histograms")]
+        if let Ok(mut reads) = self.tree.pages().shared.reads.lock() {
+            reads.record(Operation::Get, start);
+        }
+        result
+    }
+
+    pub fn scan(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<BTreeIter<'_, SnapshotPages>> {
+        self.tree.scan(start, end)
+    }
+
+    //Same range as scan, yielded in descending key order
+    pub fn scan_rev(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<BTreeIter<'_, SnapshotPages>> {
+        self.tree.scan_rev(start, end)
+    }
+
+    pub(crate) fn tree(&self) -> &BTree<SnapshotPages> {
+        &self.tree
+    }
+
+    //Read every page from the file from now on, for verifying what is on disk
+    pub(crate) fn bypass_cache(&mut self) {
+        self.tree.pages_mut().cached = false;
+    }
+}
+
+impl Drop for Snapshot {
+    //The pages only this version used are handed out again by the next update
+    fn drop(&mut self) {
+        let Ok(mut published) = self.tree.pages().shared.published() else {
+            return;
+        };
+        if let Some(count) = published.readers.get_mut(&self.version) {
+            *count -= 1;
+            if *count == 0 {
+                published.readers.remove(&self.version);
+            }
+        }
+    }
+}
+
+//Flushed pages of a snapshot's tree
+pub struct SnapshotPages {
+    shared: Arc<Shared>,
+    //Number of pages in the file when the snapshot was taken
+    total_pages: u64,
+    //Version of the tree
+    version: u64,
+    //Whether pages are looked up in and added to the page cache
+    cached: bool,
+}
+
+impl Tree for SnapshotPages {
+    fn get(&self, pointer: u64) -> Result<BNode> {
+        if pointer == 0 || pointer >= self.total_pages {
+            return Err(DbError::corruption(format!(
+                "pointer {} is outside of the {} pages in the file",
+                pointer, self.total_pages
+            )));
+        }
+        if !self.cached {
+            return pager::read_page_at(&self.shared.file, self.shared.page_size, pointer);
+        }
+        if let Some(node) = cache::lock(&self.shared.cache).get(pointer, self.version) {
+            return Ok(node);
+        }
+        let node = pager::read_page_at(&self.shared.file, self.shared.page_size, pointer)?;
+        cache::lock(&self.shared.cache).insert(pointer, node.clone(), self.version);
+        Ok(node)
+    }
+
+    fn new(&mut self, _node: BNode) -> u64 {
+        unreachable!("snapshots are read-only")
+    }
+
+    fn del(&mut self, _pointer: u64) {
+        unreachable!("snapshots are read-only")
+    }
+
+    fn page_size(&self) -> usize {
+        self.shared.page_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    fn key(idx: u32) -> [u8; 4] {
+        idx.to_be_bytes()
+    }
+
+    fn entries(snapshot: &Snapshot) -> Vec<(Vec<u8>, Vec<u8>)> {
+        snapshot
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn flushed_pages(kv: &SharedKV) -> u64 {
+        kv.shared.writer.lock().unwrap().pager().flushed_pages()
+    }
+
+    //Overwrite every key with a value of the given round
+    fn overwrite(kv: &SharedKV, keys: u32, round: u8) {
+        for idx in 0..keys {
+            kv.set(&key(idx), &[round; 100]).unwrap();
+        }
+    }
+
+    #[test]
+    fn snapshot_is_stable_while_writer_commits() {
+        let path = TempPath::new("shared-stable");
+        let kv = SharedKV::open(&path).unwrap();
+        overwrite(&kv, 100, 0);
+
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                for round in 1..=3 {
+                    overwrite(&kv, 100, round);
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    let snapshot = kv.snapshot().unwrap();
+                    let first = entries(&snapshot);
+                    //Every key holds the value of one round, the same key never changes
+                    while !done.load(Ordering::Relaxed) {
+                        assert_eq!(entries(&snapshot), first);
+                    }
+                    assert_eq!(entries(&snapshot), first);
+                    assert!(snapshot.tree().check().is_ok());
+                });
+            }
+            writer.join().unwrap();
+        });
+
+        assert_eq!(kv.get(&key(0)).unwrap(), Some(vec![3; 100]));
+        assert_eq!(kv.get(&key(99)).unwrap(), Some(vec![3; 100]));
+    }
+
+    #[test]
+    fn pages_of_a_snapshot_are_reused_once_it_is_dropped() {
+        let path = TempPath::new("shared-held");
+        let kv = SharedKV::open(&path).unwrap();
+        overwrite(&kv, 100, 0);
+        let snapshot = kv.snapshot().unwrap();
+        let before = entries(&snapshot);
+
+        //The snapshot's pages are held, so the updates take new pages even from another thread
+        let pages = flushed_pages(&kv);
+        thread::scope(|scope| {
+            scope.spawn(|| overwrite(&kv, 100, 1));
+        });
+        let held = flushed_pages(&kv);
+        assert!(held > pages);
+        assert_eq!(entries(&snapshot), before);
+        assert!(snapshot.tree().check().is_ok());
+
+        //Without the snapshot the next updates find the held pages free again, the file only
+        //grows by the few pages a longer free list takes
+        drop(snapshot);
+        overwrite(&kv, 100, 2);
+        overwrite(&kv, 100, 3);
+        assert!(flushed_pages(&kv) - held < (held - pages) / 10);
+        assert_eq!(kv.get(&key(0)).unwrap(), Some(vec![3; 100]));
+    }
+
+    #[test]
+    fn snapshots_read_through_the_page_cache() {
+        let path = TempPath::new("shared-cache");
+        let kv = SharedKV::open(&path).unwrap();
+        overwrite(&kv, 100, 0);
+
+        let snapshot = kv.snapshot().unwrap();
+        assert_eq!(snapshot.get(&key(7)).unwrap(), Some(vec![0; 100]));
+        let hits = kv.cache_stats().hits;
+        assert_eq!(snapshot.get(&key(7)).unwrap(), Some(vec![0; 100]));
+        assert!(kv.cache_stats().hits > hits);
+
+        //A page cached for a newer version is no hit for an older snapshot
+        let mut cache = cache::lock(&kv.shared.cache);
+        let ptr = snapshot.tree().root();
+        let node = cache.get(ptr, snapshot.version);
+        cache.remove(ptr);
+        cache.insert(ptr, node.unwrap(), snapshot.version + 1);
+        assert!(cache.get(ptr, snapshot.version).is_none());
+        assert!(cache.get(ptr, snapshot.version + 1).is_some());
+    }
+
+    #[cfg(feature = "latency-histograms")]
+    #[test]
+    fn latency_stats_count_reads_and_updates() {
+        let path = TempPath::new("shared-latency");
+        let kv = SharedKV::open(&path).unwrap();
+        overwrite(&kv, 10, 0);
+        kv.del(&key(0)).unwrap();
+        for idx in 0..5 {
+            kv.get(&key(idx)).unwrap();
+        }
+
+        let stats = kv.latency_stats().unwrap();
+        assert_eq!(stats.put.count, 10);
+        assert_eq!(stats.del.count, 1);
+        assert_eq!(stats.get.count, 5);
+        assert!(stats.get.p50 <= stats.get.max);
+    }
+}
@@ -0,0 +1,905 @@
+#[cfg(feature = "collation")]
+use crate::collation::Collator;
+use crate::error::{DbError, Result};
+use crate::keys::{Tuple, Value};
+use crate::sql::functions;
+use crate::sql::parser::{BinaryOp, Expr, OrderBy, Select, SelectItem, Statement, UnaryOp, parse};
+use crate::table::{ColumnType, Db, TableDef};
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+//Executor of parsed statements.
+//A statement reads the rows of its table through the primary key or one secondary index: the
+//conditions of the WHERE clause joined by AND which compare a key column with a constant give
+//the range of keys to scan, equalities on the leading key columns followed by at most one
+//range on the next one. The key with the most usable conditions wins, the primary key on a
+//tie, and without any the whole table is scanned. The full WHERE clause is evaluated on every
+//row read, so the range only has to cover the matching rows.
+//Rows come out in key order, so ORDER BY only sorts when it asks for another order than the
+//scanned key, otherwise LIMIT stops the scan early. With the collation feature ORDER BY sorts
+//text in collation order, which keys holding text in byte order don't give.
+//Expressions follow SQL semantics: comparisons with NULL are NULL, AND and OR are three-valued,
+//WHERE keeps the rows it is true for, and integers are 0 for false and 1 for true.
+//Every row is inserted, updated or deleted in a transaction of its own, so a statement changing
+//several rows which fails half way keeps the changes made before the failure
+
+//Result of a statement
+#[derive(Clone, Debug, PartialEq)]
+pub enum Output {
+    //CREATE TABLE
+    Done,
+    //Number of rows INSERT, UPDATE or DELETE changed
+    Changed(u64),
+    Rows {
+        columns: Vec<String>,
+        rows: Vec<Vec<Value>>,
+    },
+}
+
+//Parse and run a single statement
+pub fn execute(db: &mut Db, sql: &str) -> Result<Output> {
+    match parse(sql)? {
+        Statement::CreateTable(def) => {
+            db.create_table(&def)?;
+            Ok(Output::Done)
+        }
+        Statement::Insert {
+            table,
+            columns,
+            rows,
+        } => insert(db, &table, columns, &rows),
+        Statement::Select(select) => self::select(db, &select),
+        Statement::Update {
+            table,
+            assignments,
+            filter,
+        } => update(db, &table, &assignments, filter.as_ref()),
+        Statement::Delete { table, filter } => delete(db, &table, filter.as_ref()),
+    }
+}
+
+fn insert(
+    db: &mut Db,
+    table: &str,
+    columns: Option<Vec<String>>,
+    rows: &[Vec<Expr>],
+) -> Result<Output> {
+    let def = existing_table(db, table)?;
+    let columns = match columns {
+        Some(columns) => columns,
+        None => def
+            .columns
+            .iter()
+            .map(|column| column.name.clone())
+            .collect(),
+    };
+    let positions = columns
+        .iter()
+        .map(|name| column_position(&def, name))
+        .collect::<Result<Vec<_>>>()?;
+
+    //Every row is evaluated before the first one is inserted
+    let rows = rows
+        .iter()
+        .map(|exprs| {
+            if exprs.len() != positions.len() {
+                return Err(DbError::InvalidQuery(format!(
+                    "{} values are given for {} columns",
+                    exprs.len(),
+                    positions.len()
+                )));
+            }
+            let mut row = vec![Value::Null; def.columns.len()];
+            for (&idx, expr) in positions.iter().zip(exprs) {
+                row[idx] = coerce(def.columns[idx].column_type, eval(expr, None)?);
+            }
+            Ok(row)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for row in &rows {
+        db.insert(table, row)?;
+    }
+    Ok(Output::Changed(rows.len() as u64))
+}
+
+fn select(db: &Db, select: &Select) -> Result<Output> {
+    let def = existing_table(db, &select.table)?;
+    let mut exprs: Vec<&Expr> = select.filter.iter().collect();
+    exprs.extend(select.order_by.iter().map(|order| &order.expr));
+    for item in &select.items {
+        if let SelectItem::Expr { expr, .. } = item {
+            exprs.push(expr);
+        }
+    }
+    check_columns(&def, &exprs)?;
+
+    let plan = Plan::new(&def, select.filter.as_ref());
+    let sorted = plan.yields_order(&def, &select.order_by);
+    let mut rows = Vec::new();
+    let mut scan = plan.rows(db, &def)?;
+    //Checked before reading a row, so LIMIT 0 reads none
+    while !(sorted && select.limit.is_some_and(|limit| rows.len() as u64 >= limit)) {
+        let Some(row) = scan.next() else {
+            break;
+        };
+        let row = row?;
+        if matches(select.filter.as_ref(), &def, &row)? {
+            rows.push(row);
+        }
+    }
+
+    if !sorted {
+        //Sort keys are evaluated once per row instead of once per comparison
+        let mut keyed = rows
+            .into_iter()
+            .map(|row| {
+                let keys = select
+                    .order_by
+                    .iter()
+                    .map(|order| eval(&order.expr, Some((&def, &row))))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((keys, row))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        keyed.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b)
+                .zip(&select.order_by)
+                .map(|((a, b), order)| match order.descending {
+                    true => sort_order(b, a),
+                    false => sort_order(a, b),
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        rows = keyed.into_iter().map(|(_, row)| row).collect();
+        if let Some(limit) = select.limit {
+            rows.truncate(limit as usize);
+        }
+    }
+
+    let mut columns = Vec::new();
+    for item in &select.items {
+        match item {
+            SelectItem::All => columns.extend(def.columns.iter().map(|column| column.name.clone())),
+            SelectItem::Expr { name, .. } => columns.push(name.clone()),
+        }
+    }
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let mut projected = Vec::with_capacity(columns.len());
+            for item in &select.items {
+                match item {
+                    SelectItem::All => projected.extend_from_slice(row),
+                    SelectItem::Expr { expr, .. } => projected.push(eval(expr, Some((&def, row)))?),
+                }
+            }
+            Ok(projected)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Output::Rows { columns, rows })
+}
+
+fn update(
+    db: &mut Db,
+    table: &str,
+    assignments: &[(String, Expr)],
+    filter: Option<&Expr>,
+) -> Result<Output> {
+    let def = existing_table(db, table)?;
+    let positions = assignments
+        .iter()
+        .map(|(name, _)| column_position(&def, name))
+        .collect::<Result<Vec<_>>>()?;
+    let mut exprs: Vec<&Expr> = filter.into_iter().collect();
+    exprs.extend(assignments.iter().map(|(_, expr)| expr));
+    check_columns(&def, &exprs)?;
+
+    //All rows are found and their new values evaluated before the first one is changed, so
+    //a change can't make a row match again further on
+    let mut changes = Vec::new();
+    for row in matching_rows(db, &def, filter)? {
+        let mut new_row = row.clone();
+        for (&idx, (_, expr)) in positions.iter().zip(assignments) {
+            new_row[idx] = coerce(
+                def.columns[idx].column_type,
+                eval(expr, Some((&def, &row)))?,
+            );
+        }
+        changes.push((row, new_row));
+    }
+
+    //Every new row is checked before the first one is written, so a value of the wrong type
+    //in any of them fails the statement without changing a row
+    for (_, new_row) in &changes {
+        def.check_row(new_row)?;
+        def.encode_row(new_row)?;
+    }
+    for (row, new_row) in &changes {
+        //A row moving to another primary key is deleted and inserted again in one transaction
+        let key: Vec<Value> = def
+            .primary_key
+            .iter()
+            .map(|name| row[def.column(name).unwrap()].clone())
+            .collect();
+        db.replace(table, &key, new_row)?;
+    }
+    Ok(Output::Changed(changes.len() as u64))
+}
+
+fn delete(db: &mut Db, table: &str, filter: Option<&Expr>) -> Result<Output> {
+    let def = existing_table(db, table)?;
+    check_columns(&def, &filter.into_iter().collect::<Vec<_>>())?;
+
+    let keys: Vec<Vec<Value>> = matching_rows(db, &def, filter)?
+        .into_iter()
+        .map(|row| {
+            def.primary_key
+                .iter()
+                .map(|name| row[def.column(name).unwrap()].clone())
+                .collect()
+        })
+        .collect();
+    for key in &keys {
+        db.delete(table, key)?;
+    }
+    Ok(Output::Changed(keys.len() as u64))
+}
+
+fn existing_table(db: &Db, name: &str) -> Result<TableDef> {
+    db.table(name)?
+        .ok_or_else(|| DbError::UnknownTable(name.to_string()))
+}
+
+fn column_position(def: &TableDef, name: &str) -> Result<usize> {
+    def.column(name)
+        .ok_or_else(|| DbError::InvalidQuery(format!("table {} has no column {}", def.name, name)))
+}
+
+//Check every column the expressions refer to exists, so a misspelled column is reported even
+//when no row is read
+fn check_columns(def: &TableDef, exprs: &[&Expr]) -> Result<()> {
+    fn visit(def: &TableDef, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Literal(_) => Ok(()),
+            Expr::Column(name) => column_position(def, name).map(|_| ()),
+            Expr::Unary(_, expr) | Expr::IsNull { expr, .. } => visit(def, expr),
+            Expr::Binary(_, left, right) => {
+                visit(def, left)?;
+                visit(def, right)
+            }
+            Expr::Call { args, .. } => args.iter().try_for_each(|arg| visit(def, arg)),
+        }
+    }
+    exprs.iter().try_for_each(|expr| visit(def, expr))
+}
+
+fn matching_rows(db: &Db, def: &TableDef, filter: Option<&Expr>) -> Result<Vec<Vec<Value>>> {
+    let mut rows = Vec::new();
+    for row in Plan::new(def, filter).rows(db, def)? {
+        let row = row?;
+        if matches(filter, def, &row)? {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+fn matches(filter: Option<&Expr>, def: &TableDef, row: &[Value]) -> Result<bool> {
+    match filter {
+        Some(filter) => Ok(truth(&eval(filter, Some((def, row)))?)? == Some(true)),
+        None => Ok(true),
+    }
+}
+
+//Integers stored in a float column become floats, every other value is stored as it is and
+//rejected by the table if it has the wrong type
+fn coerce(column_type: ColumnType, value: Value) -> Value {
+    match (column_type, value) {
+        (ColumnType::Float, Value::Int(value)) => Value::Float(value as f64),
+        (_, value) => value,
+    }
+}
+
+//Keys scanned to find the rows of a statement
+struct Plan {
+    //Secondary index scanned, the primary key when None
+    index: Option<String>,
+    //Columns of the scanned key in key order
+    columns: Vec<String>,
+    //Values the leading key columns are equal to
+    equal: Vec<Value>,
+    //Range of the key column following the equal ones
+    lower: Bound<Value>,
+    upper: Bound<Value>,
+}
+
+impl Plan {
+    fn new(def: &TableDef, filter: Option<&Expr>) -> Plan {
+        let mut conditions = Vec::new();
+        if let Some(filter) = filter {
+            collect_conditions(def, filter, &mut conditions);
+        }
+
+        let mut best = Plan::for_key(None, def.primary_key.clone(), &conditions);
+        for index in &def.indexes {
+            let mut columns = index.columns.clone();
+            columns.extend(def.primary_key.iter().cloned());
+            let plan = Plan::for_key(Some(index.name.clone()), columns, &conditions);
+            if plan.score() > best.score() {
+                best = plan;
+            }
+        }
+        best
+    }
+
+    fn for_key(index: Option<String>, columns: Vec<String>, conditions: &[Condition]) -> Plan {
+        let mut equal = Vec::new();
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+        for column in &columns {
+            let on_column = || {
+                conditions
+                    .iter()
+                    .filter(|condition| condition.column == *column)
+            };
+            if let Some(condition) = on_column().find(|condition| condition.op == BinaryOp::Eq) {
+                equal.push(condition.value.clone());
+                continue;
+            }
+            //One bound of each side is enough, the WHERE clause checks the others
+            for condition in on_column() {
+                let value = condition.value.clone();
+                match condition.op {
+                    BinaryOp::Gt => lower = Bound::Excluded(value),
+                    BinaryOp::Ge => lower = Bound::Included(value),
+                    BinaryOp::Lt => upper = Bound::Excluded(value),
+                    BinaryOp::Le => upper = Bound::Included(value),
+                    _ => {}
+                }
+            }
+            break;
+        }
+        Plan {
+            index,
+            columns,
+            equal,
+            lower,
+            upper,
+        }
+    }
+
+    //How selective the plan is, an equality narrows the range more than a range does
+    fn score(&self) -> usize {
+        let ranged = self.lower != Bound::Unbounded || self.upper != Bound::Unbounded;
+        2 * self.equal.len() + ranged as usize
+    }
+
+    //Whether the rows come out in the order ORDER BY asks for without sorting
+    fn yields_order(&self, def: &TableDef, order_by: &[OrderBy]) -> bool {
+        order_by.len() <= self.columns.len()
+            && order_by.iter().zip(&self.columns).all(|(order, column)| {
+                !order.descending
+                    && order.expr == Expr::Column(column.clone())
+                    && !collated(def, column)
+            })
+    }
+
+    fn rows<'a>(
+        &self,
+        db: &'a Db,
+        def: &TableDef,
+    ) -> Result<Box<dyn Iterator<Item = Result<Vec<Value>>> + 'a>> {
+        let bound = |bound: &Bound<Value>| -> Bound<Vec<Value>> {
+            let with = |value: &Value| {
+                let mut values = self.equal.clone();
+                values.push(value.clone());
+                values
+            };
+            match bound {
+                Bound::Included(value) => Bound::Included(with(value)),
+                Bound::Excluded(value) => Bound::Excluded(with(value)),
+                //Without a range the equal values bound both ends
+                Bound::Unbounded if self.equal.is_empty() => Bound::Unbounded,
+                Bound::Unbounded => Bound::Included(self.equal.clone()),
+            }
+        };
+        let start = bound(&self.lower);
+        let end = bound(&self.upper);
+        let start = start.as_ref().map(Vec::as_slice);
+        let end = end.as_ref().map(Vec::as_slice);
+
+        Ok(match &self.index {
+            Some(index) => Box::new(db.scan_index(&def.name, index, start, end)?),
+            None => Box::new(db.scan(&def.name, start, end)?),
+        })
+    }
+}
+
+//Condition of the WHERE clause comparing a column with a constant of the column's type
+struct Condition {
+    column: String,
+    op: BinaryOp,
+    value: Value,
+}
+
+//Find the conditions joined by AND which can bound a scan
+fn collect_conditions(def: &TableDef, expr: &Expr, conditions: &mut Vec<Condition>) {
+    let Expr::Binary(op, left, right) = expr else {
+        return;
+    };
+    let (column, op, value) = match (left.as_ref(), right.as_ref()) {
+        (_, _) if *op == BinaryOp::And => {
+            collect_conditions(def, left, conditions);
+            collect_conditions(def, right, conditions);
+            return;
+        }
+        (Expr::Column(column), Expr::Literal(value)) => (column, *op, value),
+        //5 < a is a > 5
+        (Expr::Literal(value), Expr::Column(column)) => {
+            let flipped = match op {
+                BinaryOp::Lt => BinaryOp::Gt,
+                BinaryOp::Le => BinaryOp::Ge,
+                BinaryOp::Gt => BinaryOp::Lt,
+                BinaryOp::Ge => BinaryOp::Le,
+                op => *op,
+            };
+            (column, flipped, value)
+        }
+        _ => return,
+    };
+    //Comparisons with NULL are never true, and a value of another type isn't in the key order
+    //of the column, e.g. 1.5 between the integers 1 and 2
+    let usable = matches!(
+        op,
+        BinaryOp::Eq | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+    ) && *value != Value::Null
+        && def
+            .column(column)
+            .is_some_and(|idx| def.columns[idx].column_type.accepts(value));
+    if usable {
+        conditions.push(Condition {
+            column: column.clone(),
+            op,
+            value: value.clone(),
+        });
+    }
+}
+
+//Evaluate an expression on a row of a table, or on no row for constant expressions
+fn eval(expr: &Expr, row: Option<(&TableDef, &[Value])>) -> Result<Value> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Column(name) => match row {
+            Some((def, row)) => Ok(row[column_position(def, name)?].clone()),
+            None => Err(DbError::InvalidQuery(format!(
+                "column {} can't be used here",
+                name
+            ))),
+        },
+        Expr::Unary(UnaryOp::Neg, expr) => match eval(expr, row)? {
+            Value::Null => Ok(Value::Null),
+            Value::Int(value) => value
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| DbError::InvalidQuery("integer overflow".to_string())),
+            Value::Float(value) => Ok(Value::Float(-value)),
+            other => Err(type_error("-", &[other])),
+        },
+        Expr::Unary(UnaryOp::Not, expr) => Ok(boolean(truth(&eval(expr, row)?)?.map(|b| !b))),
+        Expr::IsNull { expr, negated } => {
+            let null = eval(expr, row)? == Value::Null;
+            Ok(boolean(Some(null != *negated)))
+        }
+        Expr::Call { name, args } => {
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, row))
+                .collect::<Result<Vec<_>>>()?;
+            functions::call(name, &args).map_err(DbError::InvalidQuery)
+        }
+        Expr::Binary(op @ (BinaryOp::And | BinaryOp::Or), left, right) => {
+            let left = truth(&eval(left, row)?)?;
+            //false AND x is false and true OR x is true whatever x is
+            let decided = (*op == BinaryOp::Or) == (left == Some(true));
+            if left.is_some() && decided {
+                return Ok(boolean(left));
+            }
+            let right = truth(&eval(right, row)?)?;
+            Ok(boolean(match (op, left, right) {
+                (BinaryOp::And, Some(false), _) | (BinaryOp::And, _, Some(false)) => Some(false),
+                (BinaryOp::Or, Some(true), _) | (BinaryOp::Or, _, Some(true)) => Some(true),
+                (_, Some(_), Some(_)) => right,
+                _ => None,
+            }))
+        }
+        Expr::Binary(op, left, right) => binary(*op, eval(left, row)?, eval(right, row)?),
+    }
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+    if left == Value::Null || right == Value::Null {
+        return Ok(Value::Null);
+    }
+    let compared = || compare(&left, &right);
+    let result = match op {
+        BinaryOp::Eq => compared()?.map(Ordering::is_eq),
+        BinaryOp::Ne => compared()?.map(Ordering::is_ne),
+        BinaryOp::Lt => compared()?.map(Ordering::is_lt),
+        BinaryOp::Le => compared()?.map(Ordering::is_le),
+        BinaryOp::Gt => compared()?.map(Ordering::is_gt),
+        BinaryOp::Ge => compared()?.map(Ordering::is_ge),
+        BinaryOp::Like => {
+            return functions::call("like", &[left, right]).map_err(DbError::InvalidQuery);
+        }
+        BinaryOp::Concat => {
+            return match (left, right) {
+                (Value::Str(left), Value::Str(right)) => Ok(Value::Str(left + &right)),
+                (left, right) => Err(type_error("||", &[left, right])),
+            };
+        }
+        _ => return arithmetic(op, left, right),
+    };
+    Ok(boolean(result))
+}
+
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+    let overflow = || DbError::InvalidQuery("integer overflow".to_string());
+    match (&left, &right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            if b == 0 && matches!(op, BinaryOp::Div | BinaryOp::Rem) {
+                return Err(DbError::InvalidQuery("division by zero".to_string()));
+            }
+            let result = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Sub => a.checked_sub(b),
+                BinaryOp::Mul => a.checked_mul(b),
+                BinaryOp::Div => a.checked_div(b),
+                _ => a.checked_rem(b),
+            };
+            result.map(Value::Int).ok_or_else(overflow)
+        }
+        _ => {
+            let (Some(a), Some(b)) = (number(&left), number(&right)) else {
+                return Err(type_error(symbol(op), &[left, right]));
+            };
+            Ok(Value::Float(match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                _ => a % b,
+            }))
+        }
+    }
+}
+
+fn symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        _ => "%",
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(value) => Some(*value as f64),
+        Value::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+//Compare two values which are not NULL, None when one of them is NaN
+fn compare(left: &Value, right: &Value) -> Result<Option<Ordering>> {
+    Ok(match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+        _ => match (number(left), number(right)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => return Err(type_error("comparison", &[left.clone(), right.clone()])),
+        },
+    })
+}
+
+//Whether ORDER BY sorts the column in another order than its keys have
+fn collated(def: &TableDef, column: &str) -> bool {
+    cfg!(feature = "collation")
+        && def
+            .column(column)
+            .is_some_and(|idx| def.columns[idx].column_type == ColumnType::Str)
+}
+
+//Order of values in ORDER BY, total unlike compare: NULL and the values compare can't order
+//are ordered like in keys, so NULL comes first.
+//With the collation feature text is in collation order, texts it ranks equal in byte order
+fn sort_order(left: &Value, right: &Value) -> Ordering {
+    #[cfg(feature = "collation")]
+    if let (Value::Str(left), Value::Str(right)) = (left, right) {
+        return Collator::default()
+            .compare(left, right)
+            .then_with(|| left.cmp(right));
+    }
+    match compare(left, right) {
+        Ok(Some(ordering)) => ordering,
+        _ => Tuple::encode(std::slice::from_ref(left))
+            .cmp(&Tuple::encode(std::slice::from_ref(right))),
+    }
+}
+
+//Truth value of a condition, None for NULL
+fn truth(value: &Value) -> Result<Option<bool>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Int(value) => Ok(Some(*value != 0)),
+        Value::Float(value) => Ok(Some(*value != 0.0)),
+        other => Err(DbError::InvalidQuery(format!(
+            "{:?} is not a truth value",
+            other
+        ))),
+    }
+}
+
+fn boolean(value: Option<bool>) -> Value {
+    match value {
+        Some(value) => Value::Int(value as i64),
+        None => Value::Null,
+    }
+}
+
+fn type_error(op: &str, values: &[Value]) -> DbError {
+    DbError::InvalidQuery(format!("{} is not defined for {:?}", op, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    fn rows(db: &mut Db, sql: &str) -> Vec<Vec<Value>> {
+        match execute(db, sql).unwrap() {
+            Output::Rows { rows, .. } => rows,
+            output => panic!("{} gave {:?}", sql, output),
+        }
+    }
+
+    fn ints(values: &[i64]) -> Vec<Vec<Value>> {
+        values
+            .iter()
+            .map(|value| vec![Value::Int(*value)])
+            .collect()
+    }
+
+    //Table t with primary key (a, b) and index by_c on c
+    fn def() -> TableDef {
+        TableDef::new(
+            "t",
+            &[
+                ("a", ColumnType::Int),
+                ("b", ColumnType::Int),
+                ("c", ColumnType::Str),
+            ],
+            &["a", "b"],
+        )
+        .with_index("by_c", &["c"])
+    }
+
+    fn plan(condition: &str) -> Plan {
+        let Statement::Select(select) =
+            parse(&format!("SELECT * FROM t WHERE {}", condition)).unwrap()
+        else {
+            unreachable!()
+        };
+        Plan::new(&def(), select.filter.as_ref())
+    }
+
+    fn order_by(sql: &str) -> Vec<OrderBy> {
+        let Statement::Select(select) =
+            parse(&format!("SELECT * FROM t ORDER BY {}", sql)).unwrap()
+        else {
+            unreachable!()
+        };
+        select.order_by
+    }
+
+    #[test]
+    fn plan_uses_index_on_equality() {
+        let plan = plan("c = 'x' AND b > 1");
+        assert_eq!(plan.index.as_deref(), Some("by_c"));
+        assert_eq!(plan.columns, ["c", "a", "b"]);
+        assert_eq!(plan.equal, [Value::Str("x".to_string())]);
+        assert_eq!(
+            (plan.lower, plan.upper),
+            (Bound::Unbounded, Bound::Unbounded)
+        );
+    }
+
+    #[test]
+    fn plan_takes_prefix_equality_and_range() {
+        let plan = plan("a = 1 AND b >= 2 AND b < 5 AND c != 'x'");
+        assert_eq!(plan.index, None);
+        assert_eq!(plan.equal, [Value::Int(1)]);
+        assert_eq!(plan.lower, Bound::Included(Value::Int(2)));
+        assert_eq!(plan.upper, Bound::Excluded(Value::Int(5)));
+    }
+
+    #[test]
+    fn plan_flips_constant_on_the_left() {
+        let plan = plan("5 < a AND 9 >= a");
+        assert_eq!(plan.index, None);
+        assert!(plan.equal.is_empty());
+        assert_eq!(plan.lower, Bound::Excluded(Value::Int(5)));
+        assert_eq!(plan.upper, Bound::Included(Value::Int(9)));
+    }
+
+    #[test]
+    fn plan_ignores_unusable_conditions() {
+        for condition in [
+            "a = 1 OR a = 2",
+            "a = 1.5",
+            "a = NULL",
+            "a + 1 = 2",
+            "a != 1",
+        ] {
+            let plan = plan(condition);
+            assert_eq!(plan.score(), 0, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn order_by_sorts_only_when_needed() {
+        let def = def();
+        let by_key = plan("a > 0");
+        assert!(by_key.yields_order(&def, &[]));
+        assert!(by_key.yields_order(&def, &order_by("a")));
+        assert!(by_key.yields_order(&def, &order_by("a, b")));
+        assert!(!by_key.yields_order(&def, &order_by("b")));
+        assert!(!by_key.yields_order(&def, &order_by("a DESC")));
+        assert!(!by_key.yields_order(&def, &order_by("a, b, c")));
+        assert!(!by_key.yields_order(&def, &order_by("a + 1")));
+
+        let path = TempPath::new("exec-order-by");
+        let mut db = Db::open(&path).unwrap();
+        execute(&mut db, "CREATE TABLE t (a INT PRIMARY KEY, b INT)").unwrap();
+        execute(
+            &mut db,
+            "INSERT INTO t VALUES (3, 1), (1, 3), (2, 2), (4, NULL)",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t ORDER BY a"),
+            ints(&[1, 2, 3, 4])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t ORDER BY a DESC"),
+            ints(&[4, 3, 2, 1])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t ORDER BY b"),
+            ints(&[4, 3, 2, 1])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t WHERE a > 1 ORDER BY b DESC"),
+            ints(&[2, 3, 4])
+        );
+    }
+
+    #[test]
+    fn limit_stops_sorted_and_unsorted_scans() {
+        let path = TempPath::new("exec-limit");
+        let mut db = Db::open(&path).unwrap();
+        execute(&mut db, "CREATE TABLE t (a INT PRIMARY KEY, b INT)").unwrap();
+        execute(
+            &mut db,
+            "INSERT INTO t VALUES (1, 4), (2, 3), (3, 2), (4, 1)",
+        )
+        .unwrap();
+
+        assert_eq!(rows(&mut db, "SELECT a FROM t LIMIT 0"), ints(&[]));
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t ORDER BY a LIMIT 0"),
+            ints(&[])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t ORDER BY b LIMIT 0"),
+            ints(&[])
+        );
+        assert_eq!(rows(&mut db, "SELECT a FROM t LIMIT 2"), ints(&[1, 2]));
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t ORDER BY b LIMIT 2"),
+            ints(&[4, 3])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t WHERE b < 4 LIMIT 2"),
+            ints(&[2, 3])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a FROM t LIMIT 10"),
+            ints(&[1, 2, 3, 4])
+        );
+    }
+
+    #[test]
+    fn index_scan_finds_rows() {
+        let path = TempPath::new("exec-index-scan");
+        let mut db = Db::open(&path).unwrap();
+        execute(
+            &mut db,
+            "CREATE TABLE t (a INT, b INT, c TEXT, PRIMARY KEY (a, b), INDEX by_c (c))",
+        )
+        .unwrap();
+        execute(
+            &mut db,
+            "INSERT INTO t VALUES (1, 1, 'y'), (1, 2, 'x'), (2, 1, 'x'), (2, 2, 'z')",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&mut db, "SELECT a * 10 + b FROM t WHERE c = 'x'"),
+            ints(&[12, 21])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT b FROM t WHERE a = 2 AND b > 1"),
+            ints(&[2])
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT a * 10 + b FROM t WHERE c = 'x' AND a > 1"),
+            ints(&[21])
+        );
+    }
+
+    #[test]
+    fn invalid_update_of_primary_key_keeps_row() {
+        let path = TempPath::new("exec-update-primary-key");
+        let mut db = Db::open(&path).unwrap();
+        execute(&mut db, "CREATE TABLE t (id INT PRIMARY KEY, name TEXT)").unwrap();
+        execute(&mut db, "INSERT INTO t VALUES (1, 'a')").unwrap();
+
+        assert!(execute(&mut db, "UPDATE t SET id = 2, name = 5 WHERE id = 1").is_err());
+        let expected = vec![vec![Value::Int(1), Value::Str("a".to_string())]];
+        assert_eq!(rows(&mut db, "SELECT * FROM t"), expected);
+
+        execute(&mut db, "INSERT INTO t VALUES (2, 'b')").unwrap();
+        assert!(matches!(
+            execute(&mut db, "UPDATE t SET id = 2 WHERE id = 1"),
+            Err(DbError::DuplicateKey)
+        ));
+        assert_eq!(
+            execute(&mut db, "UPDATE t SET id = 3 WHERE id = 1").unwrap(),
+            Output::Changed(1)
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT id FROM t"),
+            vec![vec![Value::Int(2)], vec![Value::Int(3)]]
+        );
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn order_by_text_is_collated() {
+        let path = TempPath::new("exec-order-collated");
+        let mut db = Db::open(&path).unwrap();
+        execute(&mut db, "CREATE TABLE t (name TEXT PRIMARY KEY)").unwrap();
+        execute(
+            &mut db,
+            "INSERT INTO t VALUES ('zebra'), ('Zebra'), ('apple'), ('éclair'), ('eclair')",
+        )
+        .unwrap();
+
+        let names: Vec<_> = rows(&mut db, "SELECT name FROM t ORDER BY name LIMIT 4")
+            .into_iter()
+            .flatten()
+            .collect();
+        let expected = ["apple", "eclair", "éclair", "zebra"];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|name| Value::Str(name.to_string()))
+            .collect();
+        assert_eq!(names, expected);
+    }
+}
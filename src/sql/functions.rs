@@ -0,0 +1,231 @@
+use crate::keys::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Core scalar functions of the SQL layer.
+//Following SQL semantics, a NULL argument makes the result NULL except in coalesce and nullif.
+//Timestamps are Int values holding nanoseconds since the Unix epoch, the same representation
+//keys::encode_timestamp uses.
+
+//Call a function by its (case-insensitive) name
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let name = name.to_ascii_lowercase();
+
+    //Functions which handle NULL arguments themselves
+    match name.as_str() {
+        "coalesce" => {
+            let first = args.iter().find(|arg| **arg != Value::Null);
+            return Ok(first.cloned().unwrap_or(Value::Null));
+        }
+        "nullif" => {
+            let [a, b] = expect_args::<2>(&name, args)?;
+            return Ok(if a == b { Value::Null } else { a.clone() });
+        }
+        "now" => {
+            expect_args::<0>(&name, args)?;
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| err.to_string())?
+                .as_nanos();
+            return Ok(Value::Int(nanos as i64));
+        }
+        _ => {}
+    }
+
+    if args.contains(&Value::Null) {
+        return Ok(Value::Null);
+    }
+
+    match name.as_str() {
+        "length" => match expect_args::<1>(&name, args)? {
+            [Value::Str(s)] => Ok(Value::Int(s.chars().count() as i64)),
+            [Value::Bytes(b)] => Ok(Value::Int(b.len() as i64)),
+            _ => Err(type_error(&name)),
+        },
+        "lower" => match expect_args::<1>(&name, args)? {
+            [Value::Str(s)] => Ok(Value::Str(s.to_lowercase())),
+            _ => Err(type_error(&name)),
+        },
+        "upper" => match expect_args::<1>(&name, args)? {
+            [Value::Str(s)] => Ok(Value::Str(s.to_uppercase())),
+            _ => Err(type_error(&name)),
+        },
+        "substr" => substr(args),
+        "like" => match expect_args::<2>(&name, args)? {
+            [Value::Str(text), Value::Str(pattern)] => {
+                let text: Vec<char> = text.to_lowercase().chars().collect();
+                let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+                Ok(Value::Int(like(&text, &pattern) as i64))
+            }
+            _ => Err(type_error(&name)),
+        },
+        "abs" => match expect_args::<1>(&name, args)? {
+            [Value::Int(v)] => v
+                .checked_abs()
+                .map(Value::Int)
+                .ok_or_else(|| "abs: integer overflow".to_string()),
+            [Value::Float(v)] => Ok(Value::Float(v.abs())),
+            _ => Err(type_error(&name)),
+        },
+        "round" => round(args),
+        "date" => match expect_args::<1>(&name, args)? {
+            [Value::Int(nanos)] => {
+                let (year, month, day) = civil_from_days(days(*nanos));
+                Ok(Value::Str(format!("{:04}-{:02}-{:02}", year, month, day)))
+            }
+            _ => Err(type_error(&name)),
+        },
+        "time" => match expect_args::<1>(&name, args)? {
+            [Value::Int(nanos)] => {
+                let seconds = nanos.div_euclid(1_000_000_000).rem_euclid(86_400);
+                Ok(Value::Str(format!(
+                    "{:02}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )))
+            }
+            _ => Err(type_error(&name)),
+        },
+        _ => Err(format!("unknown function {}", name)),
+    }
+}
+
+fn expect_args<'a, const N: usize>(
+    name: &str,
+    args: &'a [Value],
+) -> Result<&'a [Value; N], String> {
+    args.try_into()
+        .map_err(|_| format!("{} expects {} arguments, got {}", name, N, args.len()))
+}
+
+fn type_error(name: &str) -> String {
+    format!("{}: unsupported argument types", name)
+}
+
+//substr(text, start[, length]) with a 1-based start counted in characters
+fn substr(args: &[Value]) -> Result<Value, String> {
+    let (text, start, length) = match args {
+        [Value::Str(text), Value::Int(start)] => (text, *start, None),
+        [Value::Str(text), Value::Int(start), Value::Int(length)] => (text, *start, Some(*length)),
+        [_, _] | [_, _, _] => return Err(type_error("substr")),
+        _ => {
+            return Err(format!(
+                "substr expects 2 or 3 arguments, got {}",
+                args.len()
+            ));
+        }
+    };
+
+    let skip = (start - 1).max(0) as usize;
+    let chars = text.chars().skip(skip);
+    let result = match length {
+        Some(length) => chars.take(length.max(0) as usize).collect(),
+        None => chars.collect(),
+    };
+    Ok(Value::Str(result))
+}
+
+//round(number[, digits]), rounding half away from zero
+fn round(args: &[Value]) -> Result<Value, String> {
+    let (number, digits) = match args {
+        [number] => (number, 0),
+        [number, Value::Int(digits)] => (number, *digits),
+        [_, _] => return Err(type_error("round")),
+        _ => {
+            return Err(format!(
+                "round expects 1 or 2 arguments, got {}",
+                args.len()
+            ));
+        }
+    };
+
+    let number = match number {
+        Value::Int(v) => *v as f64,
+        Value::Float(v) => *v,
+        _ => return Err(type_error("round")),
+    };
+    let scale = 10f64.powi(digits.clamp(-308, 308) as i32);
+    Ok(Value::Float((number * scale).round() / scale))
+}
+
+//SQL LIKE where % matches any run of characters and _ matches exactly one.
+//On a mismatch only the last % seen is retried, matching one more character than before:
+//an earlier % can't match anything more than the last one could, so this takes O(n*m)
+fn like(text: &[char], pattern: &[char]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    //Position of the last % in pattern and of the text it is retried from
+    let mut retry: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '%' {
+            retry = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+            t += 1;
+            p += 1;
+        } else if let Some((percent, from)) = retry {
+            retry = Some((percent, from + 1));
+            p = percent + 1;
+            t = from + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+//Whole days since the Unix epoch
+fn days(nanos: i64) -> i64 {
+    nanos.div_euclid(86_400 * 1_000_000_000)
+}
+
+//Convert days since the Unix epoch to a (year, month, day) date in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(text: &str, pattern: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        like(&text, &pattern)
+    }
+
+    #[test]
+    fn like_patterns() {
+        assert!(matches("", ""));
+        assert!(matches("", "%%"));
+        assert!(!matches("", "_"));
+        assert!(matches("abc", "abc"));
+        assert!(!matches("abc", "ab"));
+        assert!(!matches("ab", "abc"));
+        assert!(matches("abc", "a_c"));
+        assert!(matches("abc", "%"));
+        assert!(matches("abc", "a%"));
+        assert!(matches("abc", "%c"));
+        assert!(matches("abcbc", "a%bc"));
+        assert!(matches("abcabd", "%ab_"));
+        assert!(!matches("abcabe", "%abd"));
+        assert!(matches("mississippi", "m%iss%pi"));
+        assert!(!matches("mississippi", "m%iss%pix"));
+    }
+
+    #[test]
+    fn like_many_percents_is_fast() {
+        let text = "a".repeat(10_000);
+        let pattern = format!("{}b", "%a".repeat(50));
+        assert!(!matches(&text, &pattern));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::{DbError, Result};
+
+//Tokens of a SQL statement. Keywords are not told apart from identifiers here, the parser
+//matches identifiers against keywords case-insensitively
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    //'text' with '' standing for a single quote
+    Str(String),
+    //x'hex digits'
+    Bytes(Vec<u8>),
+    //Operators and punctuation: ( ) , ; * + - / % = != <> < <= > >= ||
+    Symbol(&'static str),
+}
+
+//Token with the byte range of the statement it was read from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+//Longest symbols first, so <= isn't read as < followed by =
+const SYMBOLS: [&str; 17] = [
+    "!=", "<>", "<=", ">=", "||", "(", ")", ",", ";", "*", "+", "-", "/", "%", "=", "<", ">",
+];
+
+pub fn tokenize(sql: &str) -> Result<Vec<Spanned>> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let byte = bytes[pos];
+        let token = if byte.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        } else if sql[pos..].starts_with("--") {
+            //Comment up to the end of the line
+            pos = sql[pos..].find('\n').map_or(bytes.len(), |end| pos + end);
+            continue;
+        } else if (byte == b'x' || byte == b'X') && bytes.get(pos + 1) == Some(&b'\'') {
+            let (text, end) = quoted(sql, pos + 1)?;
+            pos = end;
+            Token::Bytes(hex(&text)?)
+        } else if byte.is_ascii_alphabetic() || byte == b'_' {
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+            Token::Ident(sql[start..pos].to_string())
+        } else if byte == b'"' {
+            //Quoted identifier, which may be a keyword or contain any character but "
+            let end = sql[pos + 1..]
+                .find('"')
+                .ok_or_else(|| invalid(start, "unterminated quoted identifier"))?;
+            pos += end + 2;
+            Token::Ident(sql[start + 1..pos - 1].to_string())
+        } else if byte.is_ascii_digit() || (byte == b'.' && next_is_digit(bytes, pos + 1)) {
+            let (token, end) = number(sql, pos)?;
+            pos = end;
+            token
+        } else if byte == b'\'' {
+            let (text, end) = quoted(sql, pos)?;
+            pos = end;
+            Token::Str(text)
+        } else if let Some(symbol) = SYMBOLS
+            .iter()
+            .find(|symbol| sql[pos..].starts_with(**symbol))
+        {
+            pos += symbol.len();
+            Token::Symbol(symbol)
+        } else {
+            let found = sql[pos..].chars().next().unwrap();
+            return Err(invalid(start, &format!("unexpected character {:?}", found)));
+        };
+        tokens.push(Spanned {
+            token,
+            start,
+            end: pos,
+        });
+    }
+    Ok(tokens)
+}
+
+fn invalid(position: usize, reason: &str) -> DbError {
+    DbError::InvalidQuery(format!("{} at offset {}", reason, position))
+}
+
+fn next_is_digit(bytes: &[u8], pos: usize) -> bool {
+    bytes.get(pos).is_some_and(u8::is_ascii_digit)
+}
+
+//Integer or float literal starting at start, with the offset after it
+fn number(sql: &str, start: usize) -> Result<(Token, usize)> {
+    let bytes = sql.as_bytes();
+    let mut pos = start;
+    let mut float = false;
+    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+        pos += 1;
+    }
+    if pos < bytes.len() && bytes[pos] == b'.' {
+        float = true;
+        pos += 1;
+        while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+            pos += 1;
+        }
+    }
+    if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+        let sign = matches!(bytes.get(pos + 1), Some(b'+' | b'-')) as usize;
+        if next_is_digit(bytes, pos + 1 + sign) {
+            float = true;
+            pos += 1 + sign;
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                pos += 1;
+            }
+        }
+    }
+
+    let text = &sql[start..pos];
+    let token = if float {
+        Token::Float(text.parse().map_err(|_| invalid(start, "invalid number"))?)
+    } else {
+        Token::Int(
+            text.parse()
+                .map_err(|_| invalid(start, "integer doesn't fit in 64 bits"))?,
+        )
+    };
+    Ok((token, pos))
+}
+
+//Text of the quoted string starting at start, with the offset after the closing quote
+fn quoted(sql: &str, start: usize) -> Result<(String, usize)> {
+    let mut text = String::new();
+    let mut pos = start + 1;
+    loop {
+        let end = sql[pos..]
+            .find('\'')
+            .ok_or_else(|| invalid(start, "unterminated string"))?;
+        text.push_str(&sql[pos..pos + end]);
+        pos += end + 1;
+        if !sql[pos..].starts_with('\'') {
+            return Ok((text, pos));
+        }
+        text.push('\'');
+        pos += 1;
+    }
+}
+
+fn hex(text: &str) -> Result<Vec<u8>> {
+    let digits = text.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return Err(DbError::InvalidQuery(format!(
+            "blob literal {} has an odd number of digits",
+            text
+        )));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| DbError::InvalidQuery(format!("invalid blob literal {}", text)))
+        })
+        .collect()
+}
@@ -0,0 +1,8 @@
+//SQL layer on top of the tables: a hand-written parser and an executor for a small subset of
+//SQL, planning every statement over the primary key or a secondary index
+mod exec;
+pub mod functions;
+mod lexer;
+mod parser;
+
+pub use exec::{Output, execute};
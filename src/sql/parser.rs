@@ -0,0 +1,791 @@
+use crate::error::{DbError, Result};
+use crate::keys::Value;
+use crate::sql::lexer::{Spanned, Token, tokenize};
+use crate::table::{ColumnType, TableDef};
+use std::fmt;
+
+//Recursive descent parser for the statements the SQL layer supports:
+//  CREATE TABLE t (col type [PRIMARY KEY], ..., [PRIMARY KEY (col, ...)], [INDEX name (col, ...)])
+//  INSERT INTO t [(col, ...)] VALUES (expr, ...), ...
+//  SELECT * | expr [AS name], ... FROM t [WHERE expr] [ORDER BY expr [ASC | DESC], ...] [LIMIT n]
+//  UPDATE t SET col = expr, ... [WHERE expr]
+//  DELETE FROM t [WHERE expr]
+//Operators from the loosest to the tightest binding: OR, AND, NOT, comparisons (= != <> < <=
+//> >= LIKE IS [NOT] NULL), + - ||, * / %, unary minus. Keywords are case-insensitive
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    CreateTable(TableDef),
+    Insert {
+        table: String,
+        //Columns the values are given for, all of them in table order when None
+        columns: Option<Vec<String>>,
+        rows: Vec<Vec<Expr>>,
+    },
+    Select(Select),
+    Update {
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        filter: Option<Expr>,
+    },
+    Delete {
+        table: String,
+        filter: Option<Expr>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    pub table: String,
+    pub items: Vec<SelectItem>,
+    pub filter: Option<Expr>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectItem {
+    //* for every column of the table
+    All,
+    //Expression with the name of its result column, the alias or else the expression's text
+    Expr { expr: Expr, name: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    IsNull { expr: Box<Expr>, negated: bool },
+    //Call of a function of sql::functions
+    Call { name: String, args: Vec<Expr> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    Concat,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+//SQL text of the expression. Every operation is parenthesized and every column name quoted,
+//so the text parses back to the same expression
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(Value::Null) => write!(f, "NULL"),
+            Expr::Literal(Value::Int(value)) => write!(f, "{}", value),
+            //Debug keeps the fraction of whole numbers, so 1.0 stays a float
+            Expr::Literal(Value::Float(value)) => write!(f, "{:?}", value),
+            Expr::Literal(Value::Str(value)) => write!(f, "'{}'", value.replace('\'', "''")),
+            Expr::Literal(Value::Bytes(value)) => {
+                write!(f, "x'")?;
+                for byte in value {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            }
+            Expr::Column(name) => write!(f, "\"{}\"", name),
+            Expr::Unary(UnaryOp::Neg, expr) => write!(f, "(-{})", expr),
+            Expr::Unary(UnaryOp::Not, expr) => write!(f, "(NOT {})", expr),
+            Expr::Binary(op, left, right) => write!(f, "({} {} {})", left, op.symbol(), right),
+            Expr::IsNull { expr, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "({} IS {}NULL)", expr, not)
+            }
+            Expr::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                for (idx, arg) in args.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "OR",
+            BinaryOp::And => "AND",
+            BinaryOp::Eq => "=",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Like => "LIKE",
+            BinaryOp::Concat => "||",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        }
+    }
+}
+
+//Parse a single statement, optionally followed by a semicolon
+pub fn parse(sql: &str) -> Result<Statement> {
+    let mut parser = Parser {
+        sql,
+        tokens: tokenize(sql)?,
+        pos: 0,
+    };
+    let statement = parser.statement()?;
+    parser.eat_symbol(";");
+    if parser.pos < parser.tokens.len() {
+        return Err(parser.error("end of the statement"));
+    }
+    Ok(statement)
+}
+
+struct Parser<'a> {
+    sql: &'a str,
+    tokens: Vec<Spanned>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn statement(&mut self) -> Result<Statement> {
+        if self.eat_keyword("CREATE") {
+            self.expect_keyword("TABLE")?;
+            self.create_table()
+        } else if self.eat_keyword("INSERT") {
+            self.expect_keyword("INTO")?;
+            self.insert()
+        } else if self.eat_keyword("SELECT") {
+            Ok(Statement::Select(self.select()?))
+        } else if self.eat_keyword("UPDATE") {
+            self.update()
+        } else if self.eat_keyword("DELETE") {
+            self.expect_keyword("FROM")?;
+            let table = self.ident()?;
+            let filter = self.filter()?;
+            Ok(Statement::Delete { table, filter })
+        } else {
+            Err(self.error("CREATE, INSERT, SELECT, UPDATE or DELETE"))
+        }
+    }
+
+    fn create_table(&mut self) -> Result<Statement> {
+        let name = self.ident()?;
+        let mut columns = Vec::new();
+        let mut primary_key: Option<Vec<String>> = None;
+        let mut indexes = Vec::new();
+
+        self.expect_symbol("(")?;
+        loop {
+            let primary = if self.eat_keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                Some(self.ident_list()?)
+            } else if self.eat_keyword("INDEX") {
+                indexes.push((self.ident()?, self.ident_list()?));
+                None
+            } else {
+                let column = self.ident()?;
+                let column_type = self.column_type()?;
+                columns.push((column.clone(), column_type));
+                if self.eat_keyword("PRIMARY") {
+                    self.expect_keyword("KEY")?;
+                    Some(vec![column])
+                } else {
+                    None
+                }
+            };
+            if let Some(key) = primary {
+                if primary_key.is_some() {
+                    return Err(DbError::InvalidQuery(format!(
+                        "table {} has more than one primary key",
+                        name
+                    )));
+                }
+                primary_key = Some(key);
+            }
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+
+        let columns: Vec<_> = columns
+            .iter()
+            .map(|(name, column_type)| (name.as_str(), *column_type))
+            .collect();
+        let primary_key = primary_key.unwrap_or_default();
+        let primary_key: Vec<_> = primary_key.iter().map(String::as_str).collect();
+        let mut def = TableDef::new(&name, &columns, &primary_key);
+        for (index, index_columns) in &indexes {
+            let index_columns: Vec<_> = index_columns.iter().map(String::as_str).collect();
+            def = def.with_index(index, &index_columns);
+        }
+        Ok(Statement::CreateTable(def))
+    }
+
+    fn column_type(&mut self) -> Result<ColumnType> {
+        let column_type = match self.peek_ident().map(str::to_ascii_uppercase).as_deref() {
+            Some("INT" | "INTEGER" | "BIGINT") => ColumnType::Int,
+            Some("FLOAT" | "REAL" | "DOUBLE") => ColumnType::Float,
+            Some("BYTES" | "BLOB") => ColumnType::Bytes,
+            Some("TEXT" | "STR" | "STRING" | "VARCHAR") => ColumnType::Str,
+            _ => return Err(self.error("a column type")),
+        };
+        self.pos += 1;
+        Ok(column_type)
+    }
+
+    fn insert(&mut self) -> Result<Statement> {
+        let table = self.ident()?;
+        let columns = match self.peek_symbol("(") {
+            true => Some(self.ident_list()?),
+            false => None,
+        };
+        self.expect_keyword("VALUES")?;
+        let mut rows = Vec::new();
+        loop {
+            self.expect_symbol("(")?;
+            rows.push(self.expr_list()?);
+            self.expect_symbol(")")?;
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        Ok(Statement::Insert {
+            table,
+            columns,
+            rows,
+        })
+    }
+
+    fn select(&mut self) -> Result<Select> {
+        let mut items = Vec::new();
+        loop {
+            if self.eat_symbol("*") {
+                items.push(SelectItem::All);
+            } else {
+                let start = self.pos;
+                let expr = self.expr()?;
+                let name = match self.eat_keyword("AS") {
+                    true => self.ident()?,
+                    false => self.text(start),
+                };
+                items.push(SelectItem::Expr { expr, name });
+            }
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+
+        self.expect_keyword("FROM")?;
+        let table = self.ident()?;
+        let filter = self.filter()?;
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let expr = self.expr()?;
+                let descending = self.eat_keyword("DESC");
+                if !descending {
+                    self.eat_keyword("ASC");
+                }
+                order_by.push(OrderBy { expr, descending });
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = match self.eat_keyword("LIMIT") {
+            true => Some(
+                self.take(|token| match token {
+                    Token::Int(limit) if *limit >= 0 => Some(*limit as u64),
+                    _ => None,
+                })
+                .ok_or_else(|| self.error("a row count"))?,
+            ),
+            false => None,
+        };
+
+        Ok(Select {
+            table,
+            items,
+            filter,
+            order_by,
+            limit,
+        })
+    }
+
+    fn update(&mut self) -> Result<Statement> {
+        let table = self.ident()?;
+        self.expect_keyword("SET")?;
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.ident()?;
+            self.expect_symbol("=")?;
+            assignments.push((column, self.expr()?));
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        let filter = self.filter()?;
+        Ok(Statement::Update {
+            table,
+            assignments,
+            filter,
+        })
+    }
+
+    //Optional WHERE clause
+    fn filter(&mut self) -> Result<Option<Expr>> {
+        match self.eat_keyword("WHERE") {
+            true => Ok(Some(self.expr()?)),
+            false => Ok(None),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("OR") {
+            expr = binary(BinaryOp::Or, expr, self.and()?);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.eat_keyword("AND") {
+            expr = binary(BinaryOp::And, expr, self.not()?);
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let expr = self.sum()?;
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull {
+                expr: Box::new(expr),
+                negated,
+            });
+        }
+        if self.eat_keyword("NOT") {
+            self.expect_keyword("LIKE")?;
+            let like = binary(BinaryOp::Like, expr, self.sum()?);
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(like)));
+        }
+        if self.eat_keyword("LIKE") {
+            return Ok(binary(BinaryOp::Like, expr, self.sum()?));
+        }
+
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => BinaryOp::Eq,
+            Some(Token::Symbol("!=" | "<>")) => BinaryOp::Ne,
+            Some(Token::Symbol("<")) => BinaryOp::Lt,
+            Some(Token::Symbol("<=")) => BinaryOp::Le,
+            Some(Token::Symbol(">")) => BinaryOp::Gt,
+            Some(Token::Symbol(">=")) => BinaryOp::Ge,
+            _ => return Ok(expr),
+        };
+        self.pos += 1;
+        Ok(binary(op, expr, self.sum()?))
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let mut expr = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("+")) => BinaryOp::Add,
+                Some(Token::Symbol("-")) => BinaryOp::Sub,
+                Some(Token::Symbol("||")) => BinaryOp::Concat,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = binary(op, expr, self.product()?);
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("*")) => BinaryOp::Mul,
+                Some(Token::Symbol("/")) => BinaryOp::Div,
+                Some(Token::Symbol("%")) => BinaryOp::Rem,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = binary(op, expr, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_symbol("-") {
+            return Ok(match self.unary()? {
+                //Folded right away, so a negative constant can bound a scan like any literal
+                Expr::Literal(Value::Int(value)) => Expr::Literal(Value::Int(-value)),
+                Expr::Literal(Value::Float(value)) => Expr::Literal(Value::Float(-value)),
+                expr => Expr::Unary(UnaryOp::Neg, Box::new(expr)),
+            });
+        }
+        if self.eat_symbol("+") {
+            return self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        if self.eat_symbol("(") {
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        if let Some(literal) = self.take(|token| match token {
+            Token::Int(value) => Some(Value::Int(*value)),
+            Token::Float(value) => Some(Value::Float(*value)),
+            Token::Str(value) => Some(Value::Str(value.clone())),
+            Token::Bytes(value) => Some(Value::Bytes(value.clone())),
+            _ => None,
+        }) {
+            return Ok(Expr::Literal(literal));
+        }
+
+        let name = self.ident().map_err(|_| self.error("an expression"))?;
+        let expr = match name.to_ascii_uppercase().as_str() {
+            "NULL" => Expr::Literal(Value::Null),
+            "TRUE" => Expr::Literal(Value::Int(1)),
+            "FALSE" => Expr::Literal(Value::Int(0)),
+            _ if self.eat_symbol("(") => {
+                let args = match self.eat_symbol(")") {
+                    true => Vec::new(),
+                    false => {
+                        let args = self.expr_list()?;
+                        self.expect_symbol(")")?;
+                        args
+                    }
+                };
+                Expr::Call { name, args }
+            }
+            _ => Expr::Column(name),
+        };
+        Ok(expr)
+    }
+
+    fn expr_list(&mut self) -> Result<Vec<Expr>> {
+        let mut exprs = vec![self.expr()?];
+        while self.eat_symbol(",") {
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+
+    //Parenthesized list of names
+    fn ident_list(&mut self) -> Result<Vec<String>> {
+        self.expect_symbol("(")?;
+        let mut names = vec![self.ident()?];
+        while self.eat_symbol(",") {
+            names.push(self.ident()?);
+        }
+        self.expect_symbol(")")?;
+        Ok(names)
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        self.take(|token| match token {
+            Token::Ident(name) => Some(name.clone()),
+            _ => None,
+        })
+        .ok_or_else(|| self.error("a name"))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|spanned| &spanned.token)
+    }
+
+    fn peek_ident(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Ident(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn peek_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol)
+    }
+
+    //Take the current token if accept makes something of it
+    fn take<T>(&mut self, accept: impl FnOnce(&Token) -> Option<T>) -> Option<T> {
+        let taken = self.peek().and_then(accept);
+        self.pos += taken.is_some() as usize;
+        taken
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self
+            .peek_ident()
+            .is_some_and(|name| name.eq_ignore_ascii_case(keyword));
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        match self.eat_keyword(keyword) {
+            true => Ok(()),
+            false => Err(self.error(keyword)),
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = self.peek_symbol(symbol);
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        match self.eat_symbol(symbol) {
+            true => Ok(()),
+            false => Err(self.error(symbol)),
+        }
+    }
+
+    //Source text of the tokens from start up to the current one
+    fn text(&self, start: usize) -> String {
+        let from = self.tokens[start].start;
+        let to = self.tokens[self.pos - 1].end;
+        self.sql[from..to].to_string()
+    }
+
+    //Error for finding the current token where expected should be
+    fn error(&self, expected: &str) -> DbError {
+        let found = match self.tokens.get(self.pos) {
+            Some(spanned) => format!("{:?}", &self.sql[spanned.start..spanned.end]),
+            None => "the end of the statement".to_string(),
+        };
+        DbError::InvalidQuery(format!("expected {}, found {}", expected, found))
+    }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary(op, Box::new(left), Box::new(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(sql: &str) -> Select {
+        match parse(sql).unwrap() {
+            Statement::Select(select) => select,
+            statement => panic!("{} parsed as {:?}", sql, statement),
+        }
+    }
+
+    fn filter(condition: &str) -> Expr {
+        select(&format!("SELECT * FROM t WHERE {}", condition))
+            .filter
+            .unwrap()
+    }
+
+    fn column(name: &str) -> Box<Expr> {
+        Box::new(Expr::Column(name.to_string()))
+    }
+
+    fn int(value: i64) -> Box<Expr> {
+        Box::new(Expr::Literal(Value::Int(value)))
+    }
+
+    #[test]
+    fn expressions_round_trip() {
+        let conditions = [
+            "a = 1 AND b < 2 OR NOT c >= 3",
+            "a + b * c - d / e % f = -g",
+            "name LIKE 'it''s%' AND name NOT LIKE x'00ff'",
+            "a IS NULL OR b IS NOT NULL",
+            "upper(name || 'x') != lower(name) AND a <> 1.5",
+            "length(x) > 0 AND now() >= -2 AND c <= 2.0e3",
+            "(a OR b) AND (c OR NULL) AND TRUE",
+        ];
+        for condition in conditions {
+            let expr = filter(condition);
+            assert_eq!(filter(&expr.to_string()), expr, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        use BinaryOp::*;
+        let expected = Expr::Binary(
+            Or,
+            Box::new(Expr::Binary(
+                And,
+                Box::new(Expr::Binary(Eq, column("a"), int(1))),
+                Box::new(Expr::Unary(
+                    UnaryOp::Not,
+                    Box::new(Expr::Binary(
+                        Lt,
+                        Box::new(Expr::Binary(
+                            Add,
+                            column("b"),
+                            Box::new(Expr::Binary(Mul, int(2), column("c"))),
+                        )),
+                        int(-3),
+                    )),
+                )),
+            )),
+            Box::new(Expr::IsNull {
+                expr: column("d"),
+                negated: false,
+            }),
+        );
+        assert_eq!(
+            filter("a = 1 AND NOT b + 2 * c < -3 OR d IS NULL"),
+            expected
+        );
+    }
+
+    #[test]
+    fn select_clauses() {
+        let parsed = select("select id, name AS n, id * 2 from t order by name desc, id limit 5;");
+        assert_eq!(parsed.table, "t");
+        let names: Vec<_> = parsed
+            .items
+            .iter()
+            .map(|item| match item {
+                SelectItem::All => "*".to_string(),
+                SelectItem::Expr { name, .. } => name.clone(),
+            })
+            .collect();
+        assert_eq!(names, ["id", "n", "id * 2"]);
+        assert_eq!(parsed.filter, None);
+        assert_eq!(
+            parsed.order_by,
+            [
+                OrderBy {
+                    expr: Expr::Column("name".to_string()),
+                    descending: true
+                },
+                OrderBy {
+                    expr: Expr::Column("id".to_string()),
+                    descending: false
+                },
+            ]
+        );
+        assert_eq!(parsed.limit, Some(5));
+    }
+
+    #[test]
+    fn statements() {
+        let expected = TableDef::new(
+            "t",
+            &[
+                ("a", ColumnType::Int),
+                ("b", ColumnType::Str),
+                ("c", ColumnType::Float),
+            ],
+            &["a", "b"],
+        )
+        .with_index("by_c", &["c"]);
+        assert_eq!(
+            parse("CREATE TABLE t (a INT, b TEXT, c REAL, PRIMARY KEY (a, b), INDEX by_c (c))")
+                .unwrap(),
+            Statement::CreateTable(expected)
+        );
+
+        assert_eq!(
+            parse("INSERT INTO t (b, a) VALUES ('x', 1), ('y', 2)").unwrap(),
+            Statement::Insert {
+                table: "t".to_string(),
+                columns: Some(vec!["b".to_string(), "a".to_string()]),
+                rows: vec![
+                    vec![Expr::Literal(Value::Str("x".to_string())), *int(1)],
+                    vec![Expr::Literal(Value::Str("y".to_string())), *int(2)],
+                ],
+            }
+        );
+        assert_eq!(
+            parse("UPDATE t SET a = a + 1 WHERE b = 'x'").unwrap(),
+            Statement::Update {
+                table: "t".to_string(),
+                assignments: vec![(
+                    "a".to_string(),
+                    Expr::Binary(BinaryOp::Add, column("a"), int(1))
+                )],
+                filter: Some(filter("b = 'x'")),
+            }
+        );
+        assert_eq!(
+            parse("DELETE FROM t").unwrap(),
+            Statement::Delete {
+                table: "t".to_string(),
+                filter: None
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_statements_are_rejected() {
+        let invalid = [
+            "",
+            "DROP TABLE t",
+            "SELECT FROM t",
+            "SELECT * t",
+            "SELECT * FROM t WHERE",
+            "SELECT * FROM t LIMIT -1",
+            "SELECT * FROM t LIMIT x",
+            "SELECT * FROM t ORDER name",
+            "SELECT (a FROM t",
+            "SELECT * FROM t; SELECT * FROM t",
+            "CREATE TABLE t (a INT PRIMARY KEY, b INT PRIMARY KEY)",
+            "CREATE TABLE t (a DATE)",
+            "INSERT INTO t VALUES 1",
+            "UPDATE t SET a WHERE b = 1",
+            "SELECT 'unterminated FROM t",
+        ];
+        for sql in invalid {
+            assert!(
+                matches!(parse(sql), Err(DbError::InvalidQuery(_))),
+                "{:?} was accepted",
+                sql
+            );
+        }
+    }
+}
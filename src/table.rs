@@ -0,0 +1,1047 @@
+use crate::error::{DbError, Result};
+use crate::keys::{Tuple, Value};
+use crate::kv::{KV, Scan};
+#[cfg(feature = "latency-histograms")]
+use crate::latency::LatencyStats;
+use std::ops::Bound;
+use std::path::Path;
+
+//Tables of typed rows stored in the KV store.
+//Every table gets a numeric prefix when it is created. A row is stored under the tuple
+//(prefix, primary key values...) with the tuple of its other columns as the value, so the rows
+//of a table are next to each other in primary key order. The tuple encoding is order
+//preserving, so byte order of the keys is the order of the primary key values and a range
+//of primary keys is a range of keys in the tree.
+//Secondary indexes get prefixes of their own. An index entry is keyed by the tuple
+//(index prefix, indexed values..., primary key values...) with an empty value, so rows with
+//equal indexed values each have an entry and a range of indexed values is a range of keys too.
+//Table definitions are rows of their own in the internal catalog table, keyed by table name,
+//and the next free prefix is kept in the internal meta table.
+//Every operation runs in a write transaction, so a row and its index entries are always
+//updated together and a failed operation leaves the store unchanged
+
+//Prefixes of the internal tables, user tables start at FIRST_TABLE_PREFIX
+const META_PREFIX: i64 = 1;
+const CATALOG_PREFIX: i64 = 2;
+const FIRST_TABLE_PREFIX: i64 = 100;
+//Key of the next free table prefix in the meta table
+const NEXT_PREFIX: &str = "next_prefix";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    Float,
+    Bytes,
+    Str,
+}
+
+impl ColumnType {
+    //Return the id under which the type is persisted in the catalog
+    fn id(self) -> i64 {
+        match self {
+            ColumnType::Int => 1,
+            ColumnType::Float => 2,
+            ColumnType::Bytes => 3,
+            ColumnType::Str => 4,
+        }
+    }
+
+    fn from_id(id: i64) -> Option<ColumnType> {
+        match id {
+            1 => Some(ColumnType::Int),
+            2 => Some(ColumnType::Float),
+            3 => Some(ColumnType::Bytes),
+            4 => Some(ColumnType::Str),
+            _ => None,
+        }
+    }
+
+    //Whether value can be stored in a column of this type, NULL fits every type
+    pub(crate) fn accepts(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (_, Value::Null)
+                | (ColumnType::Int, Value::Int(_))
+                | (ColumnType::Float, Value::Float(_))
+                | (ColumnType::Bytes, Value::Bytes(_))
+                | (ColumnType::Str, Value::Str(_))
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+}
+
+//Secondary index over some columns of a table, for finding rows by other values than their
+//primary key. Indexed columns may be NULL, NULL sorts before every other value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexDef {
+    pub name: String,
+    //Names of the indexed columns, in the order the index is sorted by
+    pub columns: Vec<String>,
+    //Prefix of the keys of the index entries, assigned by create_table
+    prefix: i64,
+}
+
+//Definition of a table. Rows hold a value for every column in the order of columns, the
+//primary key columns identify a row and can't be NULL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableDef {
+    pub name: String,
+    pub columns: Vec<Column>,
+    //Names of the primary key columns, in the order the key is sorted by
+    pub primary_key: Vec<String>,
+    pub indexes: Vec<IndexDef>,
+    //Prefix of the keys of the table's rows, assigned by create_table
+    prefix: i64,
+}
+
+impl TableDef {
+    pub fn new(name: &str, columns: &[(&str, ColumnType)], primary_key: &[&str]) -> TableDef {
+        TableDef {
+            name: name.to_string(),
+            columns: columns
+                .iter()
+                .map(|(name, column_type)| Column {
+                    name: name.to_string(),
+                    column_type: *column_type,
+                })
+                .collect(),
+            primary_key: primary_key.iter().map(|name| name.to_string()).collect(),
+            indexes: Vec::new(),
+            prefix: 0,
+        }
+    }
+
+    //Add a secondary index over the given columns
+    pub fn with_index(mut self, name: &str, columns: &[&str]) -> TableDef {
+        self.indexes.push(IndexDef {
+            name: name.to_string(),
+            columns: columns.iter().map(|name| name.to_string()).collect(),
+            prefix: 0,
+        });
+        self
+    }
+
+    //Position of the column with the given name
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    pub fn index(&self, name: &str) -> Option<&IndexDef> {
+        self.indexes.iter().find(|index| index.name == name)
+    }
+
+    //Positions of the named columns. Only valid for names of a checked definition
+    fn positions(&self, names: &[String]) -> Vec<usize> {
+        names
+            .iter()
+            .map(|name| self.column(name).unwrap())
+            .collect()
+    }
+
+    //Positions of the primary key columns
+    fn key_columns(&self) -> Vec<usize> {
+        self.positions(&self.primary_key)
+    }
+
+    fn check(&self) -> Result<()> {
+        let invalid = |reason: String| Err(DbError::InvalidSchema(reason));
+        if self.name.is_empty() {
+            return invalid("table name is empty".to_string());
+        }
+        if self.columns.is_empty() {
+            return invalid(format!("table {} has no columns", self.name));
+        }
+        for (idx, column) in self.columns.iter().enumerate() {
+            if self.column(&column.name) != Some(idx) {
+                return invalid(format!("column {} is defined twice", column.name));
+            }
+        }
+        if self.primary_key.is_empty() {
+            return invalid(format!("table {} has no primary key", self.name));
+        }
+        self.check_columns("primary key", &self.primary_key)?;
+
+        for (idx, index) in self.indexes.iter().enumerate() {
+            if index.name.is_empty() {
+                return invalid(format!("index name of table {} is empty", self.name));
+            }
+            if self.indexes[..idx]
+                .iter()
+                .any(|other| other.name == index.name)
+            {
+                return invalid(format!("index {} is defined twice", index.name));
+            }
+            if index.columns.is_empty() {
+                return invalid(format!("index {} has no columns", index.name));
+            }
+            self.check_columns(&format!("index {}", index.name), &index.columns)?;
+        }
+        Ok(())
+    }
+
+    //Check the columns of the primary key or an index exist and are named once
+    fn check_columns(&self, what: &str, names: &[String]) -> Result<()> {
+        for (idx, name) in names.iter().enumerate() {
+            if self.column(name).is_none() {
+                return Err(DbError::InvalidSchema(format!(
+                    "column {} of the {} doesn't exist",
+                    name, what
+                )));
+            }
+            if names[..idx].contains(name) {
+                return Err(DbError::InvalidSchema(format!(
+                    "column {} is in the {} twice",
+                    name, what
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    //Check that row holds a value of the right type for every column and a primary key
+    pub(crate) fn check_row(&self, row: &[Value]) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(DbError::InvalidRow(format!(
+                "table {} has {} columns but the row has {} values",
+                self.name,
+                self.columns.len(),
+                row.len()
+            )));
+        }
+        for (column, value) in self.columns.iter().zip(row) {
+            if !column.column_type.accepts(value) {
+                return Err(DbError::InvalidRow(format!(
+                    "column {} is {:?} but the value is {:?}",
+                    column.name, column.column_type, value
+                )));
+            }
+        }
+        for idx in self.key_columns() {
+            if row[idx] == Value::Null {
+                return Err(DbError::InvalidRow(format!(
+                    "primary key column {} is NULL",
+                    self.columns[idx].name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    //Check values can be the first values of the given columns, as used for the bounds of a
+    //scan. Only the primary key columns can't be NULL
+    fn check_prefix(&self, columns: &[usize], values: &[Value]) -> Result<()> {
+        if values.len() > columns.len() {
+            return Err(DbError::InvalidRow(format!(
+                "{} values are given for {} columns",
+                values.len(),
+                columns.len()
+            )));
+        }
+        let key_columns = self.key_columns();
+        for (&idx, value) in columns.iter().zip(values) {
+            let column = &self.columns[idx];
+            let null_key = *value == Value::Null && key_columns.contains(&idx);
+            if null_key || !column.column_type.accepts(value) {
+                return Err(DbError::InvalidRow(format!(
+                    "column {} is {:?} but the value is {:?}",
+                    column.name, column.column_type, value
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    //Encode the key of the row with the given primary key values
+    fn encode_key(&self, key: &[Value]) -> Result<Vec<u8>> {
+        if key.len() != self.primary_key.len() {
+            return Err(DbError::InvalidRow(format!(
+                "primary key of table {} has {} columns but {} values are given",
+                self.name,
+                self.primary_key.len(),
+                key.len()
+            )));
+        }
+        self.check_prefix(&self.key_columns(), key)?;
+
+        let mut values = vec![Value::Int(self.prefix)];
+        values.extend_from_slice(key);
+        Ok(Tuple::encode(&values))
+    }
+
+    //Primary key values of a checked row
+    fn row_key(&self, row: &[Value]) -> Vec<Value> {
+        self.key_columns()
+            .into_iter()
+            .map(|idx| row[idx].clone())
+            .collect()
+    }
+
+    //Split a checked row into its encoded key and value
+    pub(crate) fn encode_row(&self, row: &[Value]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let key_columns = self.key_columns();
+        let rest: Vec<Value> = (0..row.len())
+            .filter(|idx| !key_columns.contains(idx))
+            .map(|idx| row[idx].clone())
+            .collect();
+        Ok((self.encode_key(&self.row_key(row))?, Tuple::encode(&rest)))
+    }
+
+    //Key of the entry of a checked row in index
+    fn index_key(&self, index: &IndexDef, row: &[Value]) -> Vec<u8> {
+        let mut values = vec![Value::Int(index.prefix)];
+        values.extend(
+            self.positions(&index.columns)
+                .into_iter()
+                .map(|idx| row[idx].clone()),
+        );
+        values.extend(self.row_key(row));
+        Tuple::encode(&values)
+    }
+
+    //Primary key values of the row an index entry belongs to
+    fn decode_index_key(&self, index: &IndexDef, key: &[u8]) -> Result<Vec<Value>> {
+        match Tuple::decode(key) {
+            Some(values)
+                if values.first() == Some(&Value::Int(index.prefix))
+                    && values.len() == 1 + index.columns.len() + self.primary_key.len() =>
+            {
+                Ok(values[1 + index.columns.len()..].to_vec())
+            }
+            _ => Err(DbError::corruption(format!(
+                "entry of index {} can't be decoded",
+                index.name
+            ))),
+        }
+    }
+
+    //Reassemble a row from the primary key it was looked up with and its stored value
+    fn decode_row(&self, key: &[Value], val: &[u8]) -> Result<Vec<Value>> {
+        let key_columns = self.key_columns();
+        let mut rest = Tuple::decode(val)
+            .filter(|rest| rest.len() + key.len() == self.columns.len())
+            .ok_or_else(|| {
+                DbError::corruption(format!("row of table {} can't be decoded", self.name))
+            })?
+            .into_iter();
+
+        let row = (0..self.columns.len())
+            .map(
+                |idx| match key_columns.iter().position(|&column| column == idx) {
+                    Some(position) => key[position].clone(),
+                    None => rest.next().unwrap(),
+                },
+            )
+            .collect();
+        Ok(row)
+    }
+
+    //Reassemble a row from its stored key and value
+    fn decode_stored_row(&self, key: &[u8], val: &[u8]) -> Result<Vec<Value>> {
+        match Tuple::decode(key).as_deref() {
+            Some([Value::Int(prefix), key @ ..])
+                if *prefix == self.prefix && key.len() == self.primary_key.len() =>
+            {
+                self.decode_row(key, val)
+            }
+            _ => Err(DbError::corruption(format!(
+                "key of a row of table {} can't be decoded",
+                self.name
+            ))),
+        }
+    }
+
+    //Catalog entry of the definition: name, prefix, the number of columns followed by the
+    //name and type of each, the number of primary key columns followed by their names, then
+    //for each index its name, prefix, number of columns and their names
+    fn encode(&self) -> Vec<u8> {
+        let mut values = vec![
+            Value::Str(self.name.clone()),
+            Value::Int(self.prefix),
+            Value::Int(self.columns.len() as i64),
+        ];
+        for column in &self.columns {
+            values.push(Value::Str(column.name.clone()));
+            values.push(Value::Int(column.column_type.id()));
+        }
+        values.push(Value::Int(self.primary_key.len() as i64));
+        values.extend(self.primary_key.iter().cloned().map(Value::Str));
+        for index in &self.indexes {
+            values.push(Value::Str(index.name.clone()));
+            values.push(Value::Int(index.prefix));
+            values.push(Value::Int(index.columns.len() as i64));
+            values.extend(index.columns.iter().cloned().map(Value::Str));
+        }
+        Tuple::encode(&values)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<TableDef> {
+        let corrupted = || DbError::corruption("table definition in the catalog can't be decoded");
+        let values = Tuple::decode(bytes).ok_or_else(corrupted)?;
+        let mut fields = Fields(values.into_iter());
+        let def = TableDef::decode_fields(&mut fields).ok_or_else(corrupted)?;
+        def.check().map_err(|_| corrupted())?;
+        Ok(def)
+    }
+
+    fn decode_fields(fields: &mut Fields) -> Option<TableDef> {
+        let name = fields.str()?;
+        let prefix = fields.int()?;
+        let columns = (0..fields.int()?)
+            .map(|_| {
+                Some(Column {
+                    name: fields.str()?,
+                    column_type: ColumnType::from_id(fields.int()?)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let primary_key = (0..fields.int()?)
+            .map(|_| fields.str())
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut indexes = Vec::new();
+        while let Some(name) = fields.next_str()? {
+            let prefix = fields.int()?;
+            let columns = (0..fields.int()?)
+                .map(|_| fields.str())
+                .collect::<Option<Vec<_>>>()?;
+            indexes.push(IndexDef {
+                name,
+                columns,
+                prefix,
+            });
+        }
+
+        Some(TableDef {
+            name,
+            columns,
+            primary_key,
+            indexes,
+            prefix,
+        })
+    }
+}
+
+//Fields of a catalog entry read in order, None when the next one has another type
+struct Fields(std::vec::IntoIter<Value>);
+
+impl Fields {
+    fn str(&mut self) -> Option<String> {
+        match self.0.next()? {
+            Value::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn int(&mut self) -> Option<i64> {
+        match self.0.next()? {
+            Value::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    //Some(None) at the end of the entry
+    fn next_str(&mut self) -> Option<Option<String>> {
+        match self.0.next() {
+            None => Some(None),
+            Some(Value::Str(value)) => Some(Some(value)),
+            Some(_) => None,
+        }
+    }
+}
+
+//Database of tables, stored in a KV store of its own
+pub struct Db {
+    kv: KV,
+}
+
+impl Db {
+    pub fn open(path: impl AsRef<Path>) -> Result<Db> {
+        Ok(Db {
+            kv: KV::open(path)?,
+        })
+    }
+
+    //Open the database after checking the file for damage, see KV::open_with_selftest
+    pub fn open_with_selftest(path: impl AsRef<Path>) -> Result<Db> {
+        Ok(Db {
+            kv: KV::open_with_selftest(path)?,
+        })
+    }
+
+    pub fn close(self) -> Result<()> {
+        self.kv.close()
+    }
+
+    //Latency percentiles of the operations on the underlying store, see KV::latency_stats.
+    //A row update shows up as a put or del of every key it touches and one commit
+    #[cfg(feature = "latency-histograms")]
+    pub fn latency_stats(&self) -> LatencyStats {
+        self.kv.latency_stats()
+    }
+
+    //Create a table, assigning the next free prefixes to it and its indexes
+    pub fn create_table(&mut self, def: &TableDef) -> Result<()> {
+        def.check()?;
+        let catalog_key = catalog_key(&def.name);
+
+        let mut tx = self.kv.begin_write()?;
+        if tx.get(&catalog_key)?.is_some() {
+            return Err(DbError::TableExists(def.name.clone()));
+        }
+        let next_key = Tuple::encode(&[Value::Int(META_PREFIX), Value::Str(NEXT_PREFIX.into())]);
+        let prefix = match tx.get(&next_key)? {
+            None => FIRST_TABLE_PREFIX,
+            Some(bytes) => match Tuple::decode(&bytes).as_deref() {
+                Some([Value::Int(prefix)]) => *prefix,
+                _ => return Err(DbError::corruption("next table prefix can't be decoded")),
+            },
+        };
+
+        let mut def = TableDef {
+            prefix,
+            ..def.clone()
+        };
+        for (idx, index) in def.indexes.iter_mut().enumerate() {
+            index.prefix = prefix + 1 + idx as i64;
+        }
+        let next = prefix + 1 + def.indexes.len() as i64;
+        tx.set(&next_key, &Tuple::encode(&[Value::Int(next)]))?;
+        tx.set(&catalog_key, &def.encode())?;
+        tx.commit()
+    }
+
+    //Definition of the table with the given name
+    pub fn table(&self, name: &str) -> Result<Option<TableDef>> {
+        match self.kv.get(&catalog_key(name))? {
+            Some(bytes) => Ok(Some(TableDef::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    //Look up a row by its primary key values
+    pub fn get(&self, table: &str, key: &[Value]) -> Result<Option<Vec<Value>>> {
+        let def = self.existing_table(table)?;
+        match self.kv.get(&def.encode_key(key)?)? {
+            Some(val) => Ok(Some(def.decode_row(key, &val)?)),
+            None => Ok(None),
+        }
+    }
+
+    //Iterate over the rows with primary keys between start and end in primary key order.
+    //Bounds may give only the first primary key columns: an included start or end then
+    //includes every row starting with these values, an excluded one leaves them all out
+    pub fn scan(
+        &self,
+        table: &str,
+        start: Bound<&[Value]>,
+        end: Bound<&[Value]>,
+    ) -> Result<Rows<'_>> {
+        let def = self.existing_table(table)?;
+        let key_columns = def.key_columns();
+        let check = |values: &[Value]| def.check_prefix(&key_columns, values);
+        let (start, end) = key_range(def.prefix, start, end, check)?;
+
+        let scan = self
+            .kv
+            .begin_read()?
+            .scan(Bound::Included(&start), Bound::Excluded(&end))?;
+        Ok(Rows { def, scan })
+    }
+
+    //Find the rows whose indexed columns start with values, in index order
+    pub fn get_by_index(
+        &self,
+        table: &str,
+        index: &str,
+        values: &[Value],
+    ) -> Result<Vec<Vec<Value>>> {
+        self.scan_index(
+            table,
+            index,
+            Bound::Included(values),
+            Bound::Included(values),
+        )?
+        .collect()
+    }
+
+    //Iterate over the rows with indexed values between start and end in index order, rows
+    //with equal indexed values are ordered by primary key. Bounds hold values of the indexed
+    //columns followed by the primary key columns and may give only the first ones, like the
+    //bounds of scan
+    pub fn scan_index(
+        &self,
+        table: &str,
+        index: &str,
+        start: Bound<&[Value]>,
+        end: Bound<&[Value]>,
+    ) -> Result<IndexRows<'_>> {
+        let def = self.existing_table(table)?;
+        let index = def
+            .index(index)
+            .cloned()
+            .ok_or_else(|| DbError::InvalidSchema(format!("index {} doesn't exist", index)))?;
+        let mut columns = def.positions(&index.columns);
+        columns.extend(def.key_columns());
+        let check = |values: &[Value]| def.check_prefix(&columns, values);
+        let (start, end) = key_range(index.prefix, start, end, check)?;
+
+        let scan = self
+            .kv
+            .begin_read()?
+            .scan(Bound::Included(&start), Bound::Excluded(&end))?;
+        Ok(IndexRows {
+            def,
+            index,
+            kv: &self.kv,
+            scan,
+        })
+    }
+
+    //Add a row, failing with DuplicateKey when its primary key is taken
+    pub fn insert(&mut self, table: &str, row: &[Value]) -> Result<()> {
+        let def = self.existing_table(table)?;
+        def.check_row(row)?;
+        let (key, val) = def.encode_row(row)?;
+
+        let mut tx = self.kv.begin_write()?;
+        if tx.get(&key)?.is_some() {
+            return Err(DbError::DuplicateKey);
+        }
+        tx.set(&key, &val)?;
+        for index in &def.indexes {
+            tx.set(&def.index_key(index, row), &[])?;
+        }
+        tx.commit()
+    }
+
+    //Replace the row with the same primary key, returns whether there was one
+    pub fn update(&mut self, table: &str, row: &[Value]) -> Result<bool> {
+        let def = self.existing_table(table)?;
+        def.check_row(row)?;
+        self.replace(table, &def.row_key(row), row)
+    }
+
+    //Replace the row with the given primary key values by row, which may have another primary
+    //key. The old row is deleted and the new one inserted in the same transaction, so a
+    //failure leaves the old row in place. Fails with DuplicateKey when the new primary key
+    //belongs to another row, returns whether there was a row to replace
+    pub fn replace(&mut self, table: &str, key: &[Value], row: &[Value]) -> Result<bool> {
+        let def = self.existing_table(table)?;
+        def.check_row(row)?;
+        let old_key = def.encode_key(key)?;
+        let (new_key, val) = def.encode_row(row)?;
+
+        let mut tx = self.kv.begin_write()?;
+        let Some(old_val) = tx.get(&old_key)? else {
+            return Ok(false);
+        };
+        let old_row = def.decode_row(key, &old_val)?;
+        if new_key != old_key {
+            if tx.get(&new_key)?.is_some() {
+                return Err(DbError::DuplicateKey);
+            }
+            tx.del(&old_key)?;
+        }
+        tx.set(&new_key, &val)?;
+        for index in &def.indexes {
+            let old_entry = def.index_key(index, &old_row);
+            let new_entry = def.index_key(index, row);
+            if old_entry != new_entry {
+                tx.del(&old_entry)?;
+                tx.set(&new_entry, &[])?;
+            }
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    //Delete the row with the given primary key values, returns whether there was one
+    pub fn delete(&mut self, table: &str, key: &[Value]) -> Result<bool> {
+        let def = self.existing_table(table)?;
+        let encoded = def.encode_key(key)?;
+
+        let mut tx = self.kv.begin_write()?;
+        let Some(old_val) = tx.get(&encoded)? else {
+            return Ok(false);
+        };
+        let old_row = def.decode_row(key, &old_val)?;
+        tx.del(&encoded)?;
+        for index in &def.indexes {
+            tx.del(&def.index_key(index, &old_row))?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn existing_table(&self, name: &str) -> Result<TableDef> {
+        self.table(name)?
+            .ok_or_else(|| DbError::UnknownTable(name.to_string()))
+    }
+}
+
+fn catalog_key(name: &str) -> Vec<u8> {
+    Tuple::encode(&[Value::Int(CATALOG_PREFIX), Value::Str(name.to_string())])
+}
+
+//Keys from start up to but not including end covering the tuples (prefix, values...) between
+//the bounds. A bound with fewer values than the key has columns covers every key starting
+//with them, check validates the values of each bound
+fn key_range(
+    prefix: i64,
+    start: Bound<&[Value]>,
+    end: Bound<&[Value]>,
+    check: impl Fn(&[Value]) -> Result<()>,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    //Key of the start or end of the keys starting with values
+    let bound = |values: &[Value], after: bool| -> Result<Vec<u8>> {
+        check(values)?;
+        let mut key = vec![Value::Int(prefix)];
+        key.extend_from_slice(values);
+        Ok(match after {
+            true => Tuple::encode_prefix_end(&key),
+            false => Tuple::encode(&key),
+        })
+    };
+    let start = match start {
+        Bound::Included(values) => bound(values, false)?,
+        Bound::Excluded(values) => bound(values, true)?,
+        Bound::Unbounded => bound(&[], false)?,
+    };
+    let end = match end {
+        Bound::Included(values) => bound(values, true)?,
+        Bound::Excluded(values) => bound(values, false)?,
+        Bound::Unbounded => bound(&[], true)?,
+    };
+    Ok((start, end))
+}
+
+//Rows of a table in primary key order, created by Db::scan
+pub struct Rows<'a> {
+    def: TableDef,
+    scan: Scan<'a>,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, val) = match self.scan.next()? {
+            Ok(pair) => pair,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.def.decode_stored_row(&key, &val))
+    }
+}
+
+//Rows of a table in index order, created by Db::scan_index. Every entry is followed to its row
+pub struct IndexRows<'a> {
+    def: TableDef,
+    index: IndexDef,
+    kv: &'a KV,
+    scan: Scan<'a>,
+}
+
+impl IndexRows<'_> {
+    fn row(&self, entry: &[u8]) -> Result<Vec<Value>> {
+        let key = self.def.decode_index_key(&self.index, entry)?;
+        match self.kv.get(&self.def.encode_key(&key)?)? {
+            Some(val) => self.def.decode_row(&key, &val),
+            None => Err(DbError::corruption(format!(
+                "entry of index {} points to a missing row",
+                self.index.name
+            ))),
+        }
+    }
+}
+
+impl Iterator for IndexRows<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entry, _) = match self.scan.next()? {
+            Ok(pair) => pair,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.row(&entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    fn str(text: &str) -> Value {
+        Value::Str(text.to_string())
+    }
+
+    //Table whose primary key is its last column and not its first
+    fn people() -> TableDef {
+        TableDef::new(
+            "people",
+            &[
+                ("name", ColumnType::Str),
+                ("age", ColumnType::Int),
+                ("score", ColumnType::Float),
+                ("photo", ColumnType::Bytes),
+                ("id", ColumnType::Int),
+            ],
+            &["id"],
+        )
+        .with_index("by_age", &["age"])
+    }
+
+    fn person(id: i64, name: &str, age: i64) -> Vec<Value> {
+        vec![
+            str(name),
+            Value::Int(age),
+            Value::Float(id as f64 / 2.0),
+            Value::Bytes(vec![0, id as u8, 0xff]),
+            Value::Int(id),
+        ]
+    }
+
+    fn rows(db: &Db, table: &str) -> Vec<Vec<Value>> {
+        db.scan(table, Bound::Unbounded, Bound::Unbounded)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn rows_round_trip_through_their_encoding() {
+        let def = TableDef {
+            prefix: 100,
+            ..TableDef::new(
+                "t",
+                &[
+                    ("a", ColumnType::Str),
+                    ("b", ColumnType::Int),
+                    ("c", ColumnType::Bytes),
+                ],
+                &["b", "a"],
+            )
+        };
+        for row in [
+            vec![str("x"), Value::Int(-1), Value::Bytes(vec![0, 0])],
+            vec![str(""), Value::Int(i64::MAX), Value::Null],
+        ] {
+            def.check_row(&row).unwrap();
+            let (key, val) = def.encode_row(&row).unwrap();
+            assert_eq!(def.decode_stored_row(&key, &val).unwrap(), row);
+            assert_eq!(def.decode_row(&def.row_key(&row), &val).unwrap(), row);
+        }
+
+        //Keys of another table or with a missing column are no rows of this one
+        let (key, val) = def
+            .encode_row(&[str("x"), Value::Int(1), Value::Null])
+            .unwrap();
+        let other = TableDef {
+            prefix: 101,
+            ..def.clone()
+        };
+        assert!(other.decode_stored_row(&key, &val).is_err());
+        assert!(def.decode_row(&[Value::Int(1)], &val).is_err());
+    }
+
+    #[test]
+    fn duplicate_key_is_rejected() {
+        let path = TempPath::new("table-duplicate");
+        let mut db = Db::open(&path).unwrap();
+        db.create_table(&people()).unwrap();
+        db.insert("people", &person(1, "ann", 30)).unwrap();
+
+        let result = db.insert("people", &person(1, "bob", 40));
+        assert!(matches!(result, Err(DbError::DuplicateKey)));
+        assert_eq!(rows(&db, "people"), [person(1, "ann", 30)]);
+        assert_eq!(
+            db.get_by_index("people", "by_age", &[Value::Int(40)])
+                .unwrap(),
+            Vec::<Vec<Value>>::new()
+        );
+    }
+
+    #[test]
+    fn catalog_survives_reopening() {
+        let path = TempPath::new("table-reopen");
+        let mut db = Db::open(&path).unwrap();
+        db.create_table(&people()).unwrap();
+        let pets = TableDef::new("pets", &[("name", ColumnType::Str)], &["name"]);
+        db.create_table(&pets).unwrap();
+        db.insert("people", &person(1, "ann", 30)).unwrap();
+        db.insert("pets", &[str("rex")]).unwrap();
+        let created = db.table("people").unwrap().unwrap();
+        db.close().unwrap();
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.table("people").unwrap().unwrap(), created);
+        assert!(db.table("missing").unwrap().is_none());
+        assert_eq!(rows(&db, "people"), [person(1, "ann", 30)]);
+        assert!(matches!(
+            db.create_table(&pets),
+            Err(DbError::TableExists(_))
+        ));
+
+        //Tables created after reopening get prefixes of their own
+        let toys = TableDef::new("toys", &[("name", ColumnType::Str)], &["name"]);
+        db.create_table(&toys).unwrap();
+        db.insert("toys", &[str("ball")]).unwrap();
+        assert_eq!(rows(&db, "pets"), [[str("rex")]]);
+        assert_eq!(rows(&db, "toys"), [[str("ball")]]);
+        assert_eq!(rows(&db, "people").len(), 1);
+    }
+
+    #[test]
+    fn rows_of_the_wrong_shape_are_rejected() {
+        let path = TempPath::new("table-invalid");
+        let mut db = Db::open(&path).unwrap();
+        db.create_table(&people()).unwrap();
+
+        let mut wrong_type = person(1, "ann", 30);
+        wrong_type[1] = str("thirty");
+        let mut null_key = person(1, "ann", 30);
+        null_key[4] = Value::Null;
+        let short = &person(1, "ann", 30)[..4];
+        for row in [&wrong_type[..], &null_key, short] {
+            let result = db.insert("people", row);
+            assert!(matches!(result, Err(DbError::InvalidRow(_))), "{:?}", row);
+        }
+        assert!(rows(&db, "people").is_empty());
+
+        db.insert("people", &person(1, "ann", 30)).unwrap();
+        let result = db.update("people", &wrong_type);
+        assert!(matches!(result, Err(DbError::InvalidRow(_))));
+        assert!(matches!(
+            db.get("people", &[str("1")]),
+            Err(DbError::InvalidRow(_))
+        ));
+        assert!(matches!(db.get("people", &[]), Err(DbError::InvalidRow(_))));
+        assert!(matches!(
+            db.get("nobody", &[Value::Int(1)]),
+            Err(DbError::UnknownTable(_))
+        ));
+        assert_eq!(rows(&db, "people"), [person(1, "ann", 30)]);
+    }
+
+    //Rows found through the age index, in index order
+    fn by_age(db: &Db) -> Vec<Vec<Value>> {
+        db.scan_index("people", "by_age", Bound::Unbounded, Bound::Unbounded)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    //Number of entries stored for a table's index, whether or not they point to a row
+    fn index_entries(db: &Db, index: &str) -> usize {
+        let def = db.table("people").unwrap().unwrap();
+        let prefix = def.index(index).unwrap().prefix;
+        let (start, end) =
+            key_range(prefix, Bound::Unbounded, Bound::Unbounded, |_| Ok(())).unwrap();
+        let tx = db.kv.begin_read().unwrap();
+        tx.scan(Bound::Included(&start), Bound::Excluded(&end))
+            .unwrap()
+            .count()
+    }
+
+    #[test]
+    fn index_entries_follow_rows() {
+        let path = TempPath::new("table-index");
+        let mut db = Db::open(&path).unwrap();
+        db.create_table(&people()).unwrap();
+        db.insert("people", &person(1, "ann", 30)).unwrap();
+        db.insert("people", &person(2, "bob", 20)).unwrap();
+        db.insert("people", &person(3, "cid", 30)).unwrap();
+
+        //Equal indexed values are ordered by primary key
+        assert_eq!(
+            by_age(&db),
+            [
+                person(2, "bob", 20),
+                person(1, "ann", 30),
+                person(3, "cid", 30)
+            ]
+        );
+        assert_eq!(
+            db.get_by_index("people", "by_age", &[Value::Int(30)])
+                .unwrap(),
+            [person(1, "ann", 30), person(3, "cid", 30)]
+        );
+
+        //An update moves the entry to the new value
+        db.update("people", &person(1, "ann", 10)).unwrap();
+        assert_eq!(
+            db.get_by_index("people", "by_age", &[Value::Int(30)])
+                .unwrap(),
+            [person(3, "cid", 30)]
+        );
+        assert_eq!(
+            db.get_by_index("people", "by_age", &[Value::Int(10)])
+                .unwrap(),
+            [person(1, "ann", 10)]
+        );
+        //An update leaving the indexed value alone keeps the entry
+        db.update("people", &person(3, "cyd", 30)).unwrap();
+        assert_eq!(
+            db.get_by_index("people", "by_age", &[Value::Int(30)])
+                .unwrap(),
+            [person(3, "cyd", 30)]
+        );
+
+        //A delete removes the entry
+        assert!(db.delete("people", &[Value::Int(2)]).unwrap());
+        assert!(
+            db.get_by_index("people", "by_age", &[Value::Int(20)])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(by_age(&db), [person(1, "ann", 10), person(3, "cyd", 30)]);
+        assert_eq!(index_entries(&db, "by_age"), 2);
+    }
+
+    #[test]
+    fn index_entries_follow_primary_key_changes() {
+        let path = TempPath::new("table-index-key");
+        let mut db = Db::open(&path).unwrap();
+        db.create_table(&people()).unwrap();
+        db.insert("people", &person(1, "ann", 30)).unwrap();
+        db.insert("people", &person(2, "bob", 30)).unwrap();
+
+        //Same age under a new primary key: the entry has to move with the key even though
+        //the indexed value is unchanged, and now sorts after bob
+        assert!(
+            db.replace("people", &[Value::Int(1)], &person(5, "ann", 30))
+                .unwrap()
+        );
+        assert_eq!(by_age(&db), [person(2, "bob", 30), person(5, "ann", 30)]);
+        assert_eq!(index_entries(&db, "by_age"), 2);
+        assert_eq!(db.get("people", &[Value::Int(1)]).unwrap(), None);
+
+        //New key and new age at once
+        db.replace("people", &[Value::Int(2)], &person(0, "bob", 40))
+            .unwrap();
+        assert_eq!(by_age(&db), [person(5, "ann", 30), person(0, "bob", 40)]);
+        assert_eq!(
+            db.scan_index(
+                "people",
+                "by_age",
+                Bound::Included(&[Value::Int(30)]),
+                Bound::Excluded(&[Value::Int(40)]),
+            )
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap(),
+            [person(5, "ann", 30)]
+        );
+
+        //A replace failing on a taken key leaves both rows and their entries alone
+        let result = db.replace("people", &[Value::Int(5)], &person(0, "ann", 50));
+        assert!(matches!(result, Err(DbError::DuplicateKey)));
+        assert_eq!(by_age(&db), [person(5, "ann", 30), person(0, "bob", 40)]);
+        assert_eq!(index_entries(&db, "by_age"), 2);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::error::Result;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//Temporary files for sorts, spills, backfills and compaction.
+//All temporary files of a database live in one directory and together may not take more than
+//a configured number of bytes: a write growing a file past the limit fails with
+//ErrorKind::QuotaExceeded instead of filling up the disk the database file is on.
+//A file is removed when it is dropped, files left behind by a crash are removed when the
+//temp space is opened again, so the directory must not be shared by two open databases
+
+//Every temporary file is named with this prefix, other files in the directory are left alone
+const TEMP_PREFIX: &str = "database-temp-";
+
+#[derive(Clone)]
+pub struct TempSpace {
+    space: Arc<Space>,
+}
+
+struct Space {
+    dir: PathBuf,
+    //Maximum number of bytes of all temporary files together
+    limit: u64,
+    //Bytes reserved by the temporary files which are still open
+    used: AtomicU64,
+    //Number of the next temporary file
+    next: AtomicU64,
+}
+
+impl TempSpace {
+    //Open the temp space in dir, creating the directory if needed and removing the files a
+    //crash left behind
+    pub fn open(dir: impl AsRef<Path>, limit: u64) -> Result<TempSpace> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX)
+                && entry.file_type()?.is_file()
+            {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(TempSpace {
+            space: Arc::new(Space {
+                dir,
+                limit,
+                used: AtomicU64::new(0),
+                next: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    //Create a new empty temporary file
+    pub fn create(&self) -> Result<TempFile> {
+        let number = self.space.next.fetch_add(1, Ordering::Relaxed);
+        let path = self.space.dir.join(format!("{}{}", TEMP_PREFIX, number));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(TempFile {
+            file: Some(file),
+            path,
+            space: self.space.clone(),
+            position: 0,
+            size: 0,
+        })
+    }
+
+    //Bytes taken by the open temporary files
+    pub fn used(&self) -> u64 {
+        self.space.used.load(Ordering::Relaxed)
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.space.limit
+    }
+}
+
+impl Space {
+    //Reserve bytes for a growing file, failing when that would exceed the limit
+    fn reserve(&self, bytes: u64) -> io::Result<()> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|total| *total <= self.limit)
+            })
+            .map(|_| ())
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::QuotaExceeded,
+                    format!("temporary files would exceed {} bytes", self.limit),
+                )
+            })
+    }
+}
+
+//Temporary file, read and written like a regular file and removed when dropped
+pub struct TempFile {
+    //Only None while the file is dropped, it has to be closed before it can be removed on
+    //every platform
+    file: Option<File>,
+    path: PathBuf,
+    space: Arc<Space>,
+    //Position of the cursor, kept to know how far a write grows the file
+    position: u64,
+    //Bytes reserved for the file, the largest end of a write so far
+    size: u64,
+}
+
+impl TempFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    //Size of the file, counting all bytes written so far
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file.as_mut().unwrap()
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.position + buf.len() as u64;
+        if end > self.size {
+            self.space.reserve(end - self.size)?;
+            self.size = end;
+        }
+        let written = self.file().write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Read for TempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file().read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for TempFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.file().seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        self.file.take();
+        //A file which can't be removed now is removed when the temp space is opened again
+        let _ = fs::remove_file(&self.path);
+        self.space.used.fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Directory for a test's temp space, removed with everything in it when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir =
+                std::env::temp_dir().join(format!("database-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn write_past_the_limit_fails() {
+        let dir = TempDir::new("temp-limit");
+        let space = TempSpace::open(&dir.0, 100).unwrap();
+        let mut first = space.create().unwrap();
+        let mut second = space.create().unwrap();
+        first.write_all(&[1; 60]).unwrap();
+
+        let err = second.write_all(&[2; 50]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+        assert_eq!(second.len(), 0);
+        second.write_all(&[2; 40]).unwrap();
+        assert_eq!(space.used(), 100);
+
+        //Rewriting bytes which are reserved already takes nothing more
+        first.seek(SeekFrom::Start(0)).unwrap();
+        first.write_all(&[3; 60]).unwrap();
+        assert!(first.write_all(&[3]).is_err());
+        assert_eq!(space.used(), 100);
+
+        first.seek(SeekFrom::Start(0)).unwrap();
+        let mut read = Vec::new();
+        first.read_to_end(&mut read).unwrap();
+        assert_eq!(read, [3; 60]);
+    }
+
+    #[test]
+    fn dropping_a_file_frees_its_bytes() {
+        let dir = TempDir::new("temp-drop");
+        let space = TempSpace::open(&dir.0, 1000).unwrap();
+        let mut kept = space.create().unwrap();
+        kept.write_all(&[1; 300]).unwrap();
+        let mut dropped = space.create().unwrap();
+        dropped.write_all(&[2; 500]).unwrap();
+        let path = dropped.path().to_path_buf();
+        assert_eq!(space.used(), 800);
+
+        drop(dropped);
+        assert_eq!(space.used(), 300);
+        assert!(!path.exists());
+        //The freed bytes can be taken by another file
+        space.create().unwrap().write_all(&[3; 700]).unwrap();
+        assert_eq!(space.used(), 300);
+    }
+
+    #[test]
+    fn open_removes_files_left_by_a_crash() {
+        let dir = TempDir::new("temp-leftovers");
+        let space = TempSpace::open(&dir.0, 1000).unwrap();
+        let mut file = space.create().unwrap();
+        file.write_all(&[1; 10]).unwrap();
+        let leftover = file.path().to_path_buf();
+        //A crash drops nothing
+        std::mem::forget(file);
+        let other = dir.0.join("other-file");
+        fs::write(&other, b"kept").unwrap();
+
+        let space = TempSpace::open(&dir.0, 1000).unwrap();
+        assert!(!leftover.exists());
+        assert_eq!(fs::read(&other).unwrap(), b"kept");
+        assert_eq!(space.used(), 0);
+        assert_eq!(space.limit(), 1000);
+    }
+}
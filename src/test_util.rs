@@ -0,0 +1,35 @@
+use crate::wal;
+use std::path::{Path, PathBuf};
+
+//Helpers shared by the tests of several modules
+
+//Path of a database file in the temp directory, removed along with its write-ahead log when
+//dropped. Names are unique per test and process, so tests running in parallel don't share files
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    pub fn new(name: &str) -> TempPath {
+        let path =
+            std::env::temp_dir().join(format!("database-test-{}-{}", std::process::id(), name));
+        let path = TempPath(path);
+        path.remove();
+        path
+    }
+
+    fn remove(&self) {
+        let _ = std::fs::remove_file(&self.0);
+        let _ = std::fs::remove_file(wal::log_path(&self.0));
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
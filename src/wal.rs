@@ -0,0 +1,245 @@
+use crate::checksum::crc32;
+use crate::error::Result;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+//Write-ahead log kept next to the database file, at its path with -wal appended.
+//With the log enabled a flush appends every page it writes, the meta page last, to the log
+//as one record and syncs only the log. The pages are then written to the database file
+//without syncing it, so an update costs one sync of a sequential append however many pages
+//it touches, and the updates of a transaction share that sync.
+//The database file is synced at checkpoints, after which the log is emptied. Until then the
+//log holds every page written to the file since the last checkpoint, so opening the file
+//after a crash writes them again in order and ends with the meta page of the last flush
+//whose record was completely synced. A record cut short by the crash fails its checksum and
+//is ignored along with anything after it: its flush never returned.
+//
+//Record format:
+//| magic | count | page_size | pages                         | crc32 |
+//|  4B   |  4B   |    4B     | count * (ptr 8B + page bytes) |  4B   |
+//The checksum covers everything before it
+
+const RECORD_MAGIC: &[u8; 4] = b"WAL1";
+const RECORD_HEADER: usize = 12;
+
+pub(crate) struct Wal {
+    file: File,
+    //Number of bytes appended since the log was last emptied
+    size: u64,
+}
+
+//Path of the log of the database file at path
+pub(crate) fn log_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("-wal");
+    PathBuf::from(name)
+}
+
+impl Wal {
+    //Open the log at path, creating it if it doesn't exist. Records left in it have to be
+    //replayed first, new ones are appended after them
+    pub fn open(path: &Path) -> Result<Wal> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Wal { file, size })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    //Append the (pointer, page) writes of a flush as one record and sync it
+    pub fn append(&mut self, page_size: usize, pages: &[(u64, Vec<u8>)]) -> Result<()> {
+        let mut record = Vec::with_capacity(RECORD_HEADER + pages.len() * (8 + page_size) + 4);
+        record.extend_from_slice(RECORD_MAGIC);
+        record.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(page_size as u32).to_le_bytes());
+        for (ptr, page) in pages {
+            debug_assert_eq!(page.len(), page_size);
+            record.extend_from_slice(&ptr.to_le_bytes());
+            record.extend_from_slice(page);
+        }
+        record.extend_from_slice(&crc32(&record).to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(self.size))?;
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.size += record.len() as u64;
+        Ok(())
+    }
+
+    //Empty the log once the database file holds all of its pages durably. The truncation is
+    //synced before anything is appended again, so records of before it never show up again
+    //behind newer ones
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+//Write the pages of every complete record of the log at path to the database file, sync
+//it and empty the log. Nothing is done when there is no log
+pub(crate) fn replay(path: &Path, db: &mut File) -> Result<()> {
+    let mut log = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(log) => log,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut bytes = Vec::new();
+    log.read_to_end(&mut bytes)?;
+    if bytes.is_empty() {
+        return Ok(());
+    }
+
+    let mut position = 0;
+    while let Some(record) = decode_record(&bytes[position..]) {
+        position += record.length;
+        for (ptr, page) in record.pages {
+            db.seek(SeekFrom::Start(ptr * page.len() as u64))?;
+            db.write_all(page)?;
+        }
+    }
+    db.sync_data()?;
+
+    log.set_len(0)?;
+    log.sync_all()?;
+    Ok(())
+}
+
+//Pages of a record and the number of bytes it takes
+struct Record<'a> {
+    pages: Vec<(u64, &'a [u8])>,
+    length: usize,
+}
+
+//Decode the record at the start of bytes, None when it is missing, incomplete or damaged
+fn decode_record(bytes: &[u8]) -> Option<Record<'_>> {
+    if bytes.len() < RECORD_HEADER || &bytes[0..4] != RECORD_MAGIC {
+        return None;
+    }
+    let read_u32 =
+        |position: usize| u32::from_le_bytes(bytes[position..position + 4].try_into().unwrap());
+    let count = read_u32(4) as usize;
+    let page_size = read_u32(8) as usize;
+    let length = count
+        .checked_mul(8 + page_size)?
+        .checked_add(RECORD_HEADER + 4)?;
+    if bytes.len() < length {
+        return None;
+    }
+    if read_u32(length - 4) != crc32(&bytes[..length - 4]) {
+        return None;
+    }
+
+    let pages = bytes[RECORD_HEADER..length - 4]
+        .chunks(8 + page_size)
+        .map(|entry| {
+            let (ptr, page) = entry.split_at(8);
+            (u64::from_le_bytes(ptr.try_into().unwrap()), page)
+        })
+        .collect();
+    Some(Record { pages, length })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::check_file;
+    use crate::kv::KV;
+    use crate::test_util::TempPath;
+
+    //Write a with the log disabled, then b and c through the log, and put the database file
+    //back as it was before b as if the writes after the last sync never reached the disk.
+    //Returns the length of the log, which then holds one record for b and one for c
+    fn lose_unsynced_writes(path: &TempPath) -> u64 {
+        let mut kv = KV::open(path).unwrap();
+        kv.set(b"a", b"1").unwrap();
+        kv.close().unwrap();
+        let synced = std::fs::read(path).unwrap();
+
+        let mut kv = KV::open_with_wal(path).unwrap();
+        kv.set(b"b", b"2").unwrap();
+        kv.set(b"c", b"3").unwrap();
+        drop(kv);
+        std::fs::write(path, synced).unwrap();
+        std::fs::metadata(log_path(path.as_ref())).unwrap().len()
+    }
+
+    #[test]
+    fn open_replays_logged_updates() {
+        let path = TempPath::new("wal-replay");
+        lose_unsynced_writes(&path);
+
+        let kv = KV::open(&path).unwrap();
+        assert_eq!(kv.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(kv.get(b"b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(kv.get(b"c").unwrap(), Some(b"3".to_vec()));
+        let log = std::fs::metadata(log_path(path.as_ref())).unwrap();
+        assert_eq!(log.len(), 0);
+    }
+
+    #[test]
+    fn check_leaves_log_and_file_alone() {
+        let path = TempPath::new("wal-check");
+        lose_unsynced_writes(&path);
+        let file = std::fs::read(&path).unwrap();
+        let log = std::fs::read(log_path(path.as_ref())).unwrap();
+
+        let checked = check_file(&path).unwrap();
+        assert!(checked.unreplayed_log);
+        assert_eq!(checked.result.unwrap().keys, 1);
+        assert_eq!(std::fs::read(&path).unwrap(), file);
+        assert_eq!(std::fs::read(log_path(path.as_ref())).unwrap(), log);
+
+        drop(KV::open(&path).unwrap());
+        let checked = check_file(&path).unwrap();
+        assert!(!checked.unreplayed_log);
+        assert_eq!(checked.result.unwrap().keys, 3);
+    }
+
+    #[test]
+    fn torn_record_is_ignored() {
+        let path = TempPath::new("wal-torn");
+        let length = lose_unsynced_writes(&path);
+        let log = OpenOptions::new()
+            .write(true)
+            .open(log_path(path.as_ref()))
+            .unwrap();
+        log.set_len(length - 10).unwrap();
+
+        let mut kv = KV::open(&path).unwrap();
+        assert_eq!(kv.get(b"b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(kv.get(b"c").unwrap(), None);
+        kv.set(b"d", b"4").unwrap();
+        assert_eq!(kv.get(b"d").unwrap(), Some(b"4".to_vec()));
+    }
+
+    #[test]
+    fn close_empties_log() {
+        let path = TempPath::new("wal-close");
+        let mut kv = KV::open_with_wal(&path).unwrap();
+        let mut tx = kv.begin_write().unwrap();
+        for idx in 0..1000u32 {
+            tx.set(&idx.to_be_bytes(), &[7; 100]).unwrap();
+        }
+        tx.commit().unwrap();
+        assert!(kv.del(&0u32.to_be_bytes()).unwrap());
+        let log = std::fs::metadata(log_path(path.as_ref())).unwrap();
+        assert!(log.len() > 0);
+        kv.close().unwrap();
+        let log = std::fs::metadata(log_path(path.as_ref())).unwrap();
+        assert_eq!(log.len(), 0);
+
+        let kv = KV::open(&path).unwrap();
+        assert_eq!(kv.get(&0u32.to_be_bytes()).unwrap(), None);
+        assert_eq!(kv.get(&999u32.to_be_bytes()).unwrap(), Some(vec![7; 100]));
+    }
+}